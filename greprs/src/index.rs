@@ -0,0 +1,348 @@
+use std::{
+  collections::{HashMap, HashSet},
+  error::Error,
+  fs,
+  io::{BufWriter, Write},
+  path::{Path, PathBuf},
+  time::UNIX_EPOCH,
+};
+use regex_syntax::{
+  hir::{ClassUnicode, ClassUnicodeRange, Hir, HirKind, Literal, RepetitionKind, RepetitionRange},
+  Parser,
+};
+
+use crate::{collect_files, Config};
+
+// Lives inside the indexed directory. It starts with a `.`,
+// so `collect_files` never searches (or indexes) it.
+const INDEX_FILE: &str = ".greprs-index";
+// Version 1 only lowercased ASCII, those indexes get rebuilt
+const HEADER: &str = "greprs-index 2";
+// Written instead of the trigrams of a file that couldn't be read
+const UNREADABLE: &str = "?";
+
+/// Three bytes packed into one number, e.g. "abc" is 0x616263
+type Trigram = u32;
+
+struct IndexedFile {
+  // Used to tell whether a file changed since it was indexed
+  modified: u128,
+  len: u64,
+  // None when the file couldn't be read. Those are always searched, so they
+  // aren't lost if they become readable without their modified time changing.
+  trigrams: Option<HashSet<Trigram>>,
+}
+
+/// Which trigrams appear in each file below a directory.
+/// Searches use it to skip files that can't possibly match.
+pub struct Index {
+  root: PathBuf,
+  // Keyed by the path relative to `root`
+  files: HashMap<PathBuf, IndexedFile>,
+}
+
+impl Index {
+  /// Loads the index saved in `root`, if there is one
+  pub fn load(root: &Path) -> Result<Option<Index>, Box<dyn Error>> {
+    let contents = match fs::read_to_string(root.join(INDEX_FILE)) {
+      Ok(contents) => contents,
+      Err(_) => return Ok(None),
+    };
+
+    let mut lines = contents.lines();
+    if lines.next() != Some(HEADER) {
+      return Err("Unrecognised index file, rebuild it with `greprs index build`".into());
+    }
+
+    // Every file is two lines:
+    //   <modified> <len> <path>
+    //   <trigram> <trigram> ...   (or `?` if it couldn't be read)
+    let mut files = HashMap::new();
+    while let (Some(file_line), Some(trigram_line)) = (lines.next(), lines.next()) {
+      let mut parts = file_line.splitn(3, ' ');
+      let (Some(modified), Some(len), Some(path)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err("Corrupt index file, rebuild it with `greprs index build`".into());
+      };
+      let trigrams = if trigram_line == UNREADABLE {
+        None
+      } else {
+        Some(trigram_line
+          .split_whitespace()
+          .map(|trigram| Trigram::from_str_radix(trigram, 16))
+          .collect::<Result<_, _>>()?)
+      };
+      files.insert(PathBuf::from(path), IndexedFile {
+        modified: modified.parse()?,
+        len: len.parse()?,
+        trigrams,
+      });
+    }
+
+    Ok(Some(Index { root: root.to_path_buf(), files }))
+  }
+
+  pub fn save(&self) -> Result<(), Box<dyn Error>> {
+    let mut out = BufWriter::new(fs::File::create(self.root.join(INDEX_FILE))?);
+    writeln!(out, "{}", HEADER)?;
+
+    // Sorted so rebuilding an unchanged tree gives the same file
+    let mut paths: Vec<&PathBuf> = self.files.keys().collect();
+    paths.sort();
+    for path in paths {
+      let file = &self.files[path];
+      writeln!(out, "{} {} {}", file.modified, file.len, path.display())?;
+      let Some(trigrams) = &file.trigrams else {
+        writeln!(out, "{}", UNREADABLE)?;
+        continue;
+      };
+      let mut trigrams: Vec<&Trigram> = trigrams.iter().collect();
+      trigrams.sort();
+      let trigrams: Vec<String> = trigrams.iter().map(|trigram| format!("{:x}", trigram)).collect();
+      writeln!(out, "{}", trigrams.join(" "))?;
+    }
+
+    out.flush()?;
+    Ok(())
+  }
+
+  /// Brings the index in line with what's on disk: files that are new
+  /// or whose modified time or size changed get re-read, deleted files
+  /// are dropped. Returns how many files were added, changed or removed.
+  pub fn update(&mut self) -> Result<usize, Box<dyn Error>> {
+    let mut seen = HashSet::new();
+    let mut updated = 0;
+
+    for path in collect_files(&self.root) {
+      let Ok(metadata) = fs::metadata(&path) else {
+        continue;
+      };
+      let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_nanos();
+      let relative = path.strip_prefix(&self.root)?.to_path_buf();
+
+      let unchanged = self.files
+        .get(&relative)
+        .map(|file| file.modified == modified && file.len == metadata.len())
+        .unwrap_or(false);
+      if !unchanged {
+        self.files.insert(relative.clone(), IndexedFile {
+          modified,
+          len: metadata.len(),
+          trigrams: fs::read(&path).ok().map(|contents| trigrams(&contents)),
+        });
+        updated += 1;
+      }
+      seen.insert(relative);
+    }
+
+    let before = self.files.len();
+    self.files.retain(|path, _| seen.contains(path));
+    Ok(updated + before - self.files.len())
+  }
+
+  /// Files that contain every trigram of the query, so they might match,
+  /// plus the ones that couldn't be read when they were indexed
+  pub fn candidates(&self, query: &str, regex: bool) -> Vec<PathBuf> {
+    let required = if regex {
+      regex_trigrams(query)
+    } else {
+      trigrams(query.as_bytes())
+    };
+
+    let mut paths: Vec<PathBuf> = self.files
+      .iter()
+      .filter(|(_, file)| file.trigrams.as_ref().map(|trigrams| required.is_subset(trigrams)).unwrap_or(true))
+      .map(|(path, _)| self.root.join(path))
+      .collect();
+    paths.sort();
+    paths
+  }
+}
+
+/// Every trigram in `bytes`, ignoring case so that the same index works
+/// for `--ignore-case` searches. Text is case folded one character at a
+/// time (see `fold_case`), anything that isn't UTF-8 only has its ASCII lowercased.
+fn trigrams(bytes: &[u8]) -> HashSet<Trigram> {
+  let folded = std::str::from_utf8(bytes).map(fold_case);
+  let bytes = match &folded {
+    Ok(text) => text.as_bytes(),
+    Err(_) => bytes,
+  };
+  bytes
+    .windows(3)
+    .map(|window| {
+      window
+        .iter()
+        .fold(0, |trigram, byte| (trigram << 8) | byte.to_ascii_lowercase() as Trigram)
+    })
+    .collect()
+}
+
+/// Lowercases `text`, then swaps every character for the same one out of the
+/// characters it's case-insensitively equal to. Folding only ASCII isn't enough:
+/// the Kelvin sign "K" matches "k" with `--ignore-case`, but isn't ASCII itself.
+fn fold_case(text: &str) -> String {
+  text.chars().flat_map(char::to_lowercase).map(fold_char).collect()
+}
+
+fn fold_char(c: char) -> char {
+  if c.is_ascii() {
+    return c.to_ascii_lowercase();
+  }
+  // Every character that simple case folding (what regexes use
+  // for `(?i)`) treats as equal to `c`, including `c` itself
+  let mut class = ClassUnicode::new([ClassUnicodeRange::new(c, c)]);
+  class.case_fold_simple();
+  let equal: Vec<char> = class.iter().flat_map(|range| range.start()..=range.end()).collect();
+  // ASCII first, so "K" folds to the same "k" as the fast path above
+  match equal.iter().find(|c| c.is_ascii()) {
+    Some(ascii) => ascii.to_ascii_lowercase(),
+    None => equal[0],
+  }
+}
+
+/// The trigrams that any line matching the regex has to contain.
+/// An unparseable regex (or one with no long enough literals) requires nothing.
+fn regex_trigrams(query: &str) -> HashSet<Trigram> {
+  let Ok(hir) = Parser::new().parse(query) else {
+    return HashSet::new();
+  };
+
+  let mut current = Vec::new();
+  let mut runs = Vec::new();
+  required_literals(&hir, &mut current, &mut runs);
+  runs.push(current);
+
+  runs.iter().flat_map(|run| trigrams(run)).collect()
+}
+
+/// Collects runs of literal text that every match must contain.
+/// Anything that can vary (classes, alternations, optional parts) ends the current run.
+fn required_literals(hir: &Hir, current: &mut Vec<u8>, runs: &mut Vec<Vec<u8>>) {
+  match hir.kind() {
+    HirKind::Empty => {},
+    HirKind::Literal(Literal::Unicode(c)) => {
+      let mut buf = [0; 4];
+      current.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+    },
+    HirKind::Literal(Literal::Byte(b)) => current.push(*b),
+    HirKind::Group(group) => required_literals(&group.hir, current, runs),
+    HirKind::Concat(hirs) => {
+      for hir in hirs {
+        required_literals(hir, current, runs);
+      }
+    },
+    HirKind::Repetition(repetition) => {
+      runs.push(std::mem::take(current));
+      let at_least_once = match &repetition.kind {
+        RepetitionKind::ZeroOrOne | RepetitionKind::ZeroOrMore => false,
+        RepetitionKind::OneOrMore => true,
+        RepetitionKind::Range(RepetitionRange::Exactly(min))
+        | RepetitionKind::Range(RepetitionRange::AtLeast(min))
+        | RepetitionKind::Range(RepetitionRange::Bounded(min, _)) => *min > 0,
+      };
+      if at_least_once {
+        // The repeated part shows up at least once, but what comes
+        // before or after it depends on how many times it repeats
+        required_literals(&repetition.hir, current, runs);
+        runs.push(std::mem::take(current));
+      }
+    },
+    HirKind::Class(_)
+    | HirKind::Anchor(_)
+    | HirKind::WordBoundary(_)
+    | HirKind::Alternation(_) => {
+      runs.push(std::mem::take(current));
+    },
+  }
+}
+
+/// Builds (or brings up to date) the index for the directory in the config
+pub fn build(config: &Config) -> Result<(), Box<dyn Error>> {
+  let root = Path::new(&config.filepath);
+  if !root.is_dir() {
+    return Err("Only directories can be indexed".into());
+  }
+
+  // Unchanged files are reused from the existing index, if it can be read
+  let mut index = match Index::load(root) {
+    Ok(Some(index)) => index,
+    _ => Index { root: root.to_path_buf(), files: HashMap::new() },
+  };
+  let updated = index.update()?;
+  index.save()?;
+
+  println!("Indexed {} files ({} updated)", index.files.len(), updated);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn packed(trigram: &str) -> Trigram {
+    *trigrams(trigram.as_bytes()).iter().next().unwrap()
+  }
+
+  #[test]
+  fn literal_trigrams_ignore_case() {
+    let expected: HashSet<Trigram> = [packed("hel"), packed("ell"), packed("llo")].into();
+    assert_eq!(expected, trigrams(b"HeLlo"));
+  }
+
+  #[test]
+  fn regex_trigrams_only_use_required_literals() {
+    // "hello" is required, "world" is optional and [0-9] varies
+    let expected: HashSet<Trigram> = [packed("hel"), packed("ell"), packed("llo")].into();
+    assert_eq!(expected, regex_trigrams("hello[0-9]+(world)?"));
+
+    // Either side of an alternation could match, so neither is required
+    assert!(regex_trigrams("foo|bar").is_empty());
+  }
+
+  fn index_of(contents: &str) -> Index {
+    Index {
+      root: PathBuf::from("root"),
+      files: HashMap::from([(PathBuf::from("a.txt"), IndexedFile {
+        modified: 0,
+        len: 0,
+        trigrams: Some(trigrams(contents.as_bytes())),
+      })]),
+    }
+  }
+
+  #[test]
+  fn unreadable_files_are_always_candidates() {
+    let root = std::env::temp_dir().join(format!("greprs-index-unreadable-{}", std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    let mut index = index_of("hello");
+    index.root = root.clone();
+    index.files.insert(PathBuf::from("locked.txt"), IndexedFile { modified: 0, len: 0, trigrams: None });
+
+    // Still there after a save and load
+    index.save().unwrap();
+    let index = Index::load(&root).unwrap().unwrap();
+    assert_eq!(
+      vec![root.join("a.txt"), root.join("locked.txt")],
+      index.candidates("hello", false)
+    );
+    assert_eq!(vec![root.join("locked.txt")], index.candidates("goodbye", false));
+
+    fs::remove_dir_all(&root).unwrap();
+  }
+
+  #[test]
+  fn non_ascii_trigrams_ignore_case() {
+    let index = index_of("café");
+    assert_eq!(vec![PathBuf::from("root/a.txt")], index.candidates("CAFÉ", false));
+  }
+
+  #[test]
+  fn case_folding_into_ascii_still_matches() {
+    // Starts with the Kelvin sign, which `--ignore-case` treats as a "k"
+    let index = index_of("\u{212A}elvin");
+    assert_eq!(vec![PathBuf::from("root/a.txt")], index.candidates("kelvin", false));
+    assert_eq!(vec![PathBuf::from("root/a.txt")], index.candidates("kelvin", true));
+    assert_eq!(vec![PathBuf::from("root/a.txt")], index.candidates("\u{212A}elvin", false));
+    assert!(index.candidates("kelp", false).is_empty());
+  }
+}
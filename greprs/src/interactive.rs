@@ -0,0 +1,362 @@
+use std::{
+  error::Error,
+  io::{self, Stderr},
+  path::{Path, PathBuf},
+};
+use ratatui::{
+  backend::CrosstermBackend,
+  crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+  },
+  layout::{Constraint, Layout},
+  style::{Style, Stylize},
+  text::{Line, Span},
+  widgets::{Block, List, ListItem, ListState, Paragraph},
+  Frame,
+  Terminal,
+};
+use crate::{build_regex, collect_files, find_lines, read_file, Config};
+
+// How many lines to jump with PageUp/PageDown
+const PAGE_SIZE: usize = 10;
+
+/// A file that was loaded when the UI started
+struct LoadedFile {
+  path: PathBuf,
+  contents: String,
+}
+
+/// A single matching line
+struct Hit {
+  file: usize,
+  line_number: usize,
+  line: String,
+}
+
+struct App {
+  files: Vec<LoadedFile>,
+  query: String,
+  regex: bool,
+  ignore_case: bool,
+  hits: Vec<Hit>,
+  list: ListState,
+  // Set when the query is not a valid regex yet (e.g. the user is mid-way through typing it)
+  error: Option<String>,
+}
+
+impl App {
+  fn new(config: &Config) -> App {
+    // Files that can't be read as UTF-8 text are left out,
+    // the same as they would fail in a normal search
+    let files = collect_files(Path::new(&config.filepath))
+      .into_iter()
+      .filter_map(|path| {
+        read_file(config, &path)
+          .ok()
+          .map(|contents| LoadedFile { path, contents })
+      })
+      .collect();
+
+    let mut app = App {
+      files,
+      query: config.query.clone(),
+      regex: config.regex,
+      ignore_case: config.ignore_case,
+      hits: Vec::new(),
+      list: ListState::default(),
+      error: None,
+    };
+    app.refresh();
+    app
+  }
+
+  /// Re-runs the search with the current query and options
+  fn refresh(&mut self) {
+    self.hits.clear();
+    self.error = None;
+
+    if self.query.is_empty() {
+      self.list.select(None);
+      return;
+    }
+    if self.regex {
+      // regex_search unwraps the compiled regex, so check it first
+      if let Err(e) = build_regex(&self.query, self.ignore_case) {
+        self.error = Some(e.to_string());
+        self.list.select(None);
+        return;
+      }
+    }
+
+    for (i, file) in self.files.iter().enumerate() {
+      for (line_number, line) in find_lines(&self.query, &file.contents, self.regex, self.ignore_case) {
+        self.hits.push(Hit { file: i, line_number, line: line.to_string() });
+      }
+    }
+
+    self.list.select(if self.hits.is_empty() { None } else { Some(0) });
+  }
+
+  /// Moves the selection by `amount`, staying within the results
+  fn scroll(&mut self, amount: isize) {
+    if self.hits.is_empty() {
+      return;
+    }
+    let current = self.list.selected().unwrap_or(0) as isize;
+    let last = self.hits.len() as isize - 1;
+    self.list.select(Some((current + amount).clamp(0, last) as usize));
+  }
+
+  fn selected(&self) -> Option<&Hit> {
+    self.list.selected().and_then(|i| self.hits.get(i))
+  }
+
+  /// Formats a hit the way editors expect it, e.g. `src/lib.rs:42`
+  fn location(&self, hit: &Hit) -> String {
+    format!("{}:{}", self.files[hit.file].path.display(), hit.line_number)
+  }
+}
+
+/// Puts the terminal into raw mode on the alternate screen, and back again when
+/// dropped. Dropping also happens while a panic unwinds, so a bug in the UI
+/// doesn't leave the user's shell stuck in raw mode.
+struct TerminalGuard;
+
+impl TerminalGuard {
+  fn enter() -> Result<TerminalGuard, Box<dyn Error>> {
+    terminal::enable_raw_mode()?;
+    // From here on the guard undoes raw mode, even if the next line fails
+    let guard = TerminalGuard;
+    execute!(io::stderr(), EnterAlternateScreen)?;
+    Ok(guard)
+  }
+}
+
+impl Drop for TerminalGuard {
+  fn drop(&mut self) {
+    // Nothing sensible can be done if these fail, the terminal is already broken
+    let _ = terminal::disable_raw_mode();
+    let _ = execute!(io::stderr(), LeaveAlternateScreen, cursor::Show);
+  }
+}
+
+/// Runs the interactive search UI until the user picks a result or quits.
+/// Returns the chosen `file:line`, if any.
+///
+/// The UI is drawn on stderr so that stdout only ever contains the choice,
+/// which makes `vim $(greprs --interactive src)` style usage possible.
+pub fn run(config: &Config) -> Result<Option<String>, Box<dyn Error>> {
+  let mut app = App::new(config);
+
+  // Always give the terminal back when this returns, whether the loop finished, failed or panicked
+  let _guard = TerminalGuard::enter()?;
+  let mut terminal = Terminal::new(CrosstermBackend::new(io::stderr()))?;
+
+  event_loop(&mut terminal, &mut app)
+}
+
+fn event_loop(
+  terminal: &mut Terminal<CrosstermBackend<Stderr>>,
+  app: &mut App,
+) -> Result<Option<String>, Box<dyn Error>> {
+  loop {
+    terminal.draw(|frame| draw(frame, app))?;
+
+    let key = match event::read()? {
+      Event::Key(key) if key.kind == KeyEventKind::Press => key,
+      _ => continue,
+    };
+
+    if let Some(done) = handle_key(app, key) {
+      return Ok(done);
+    }
+  }
+}
+
+/// Applies a key press to the app.
+/// Returns `Some` once the UI should close, holding the choice (if one was made).
+fn handle_key(app: &mut App, key: KeyEvent) -> Option<Option<String>> {
+  let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+  match key.code {
+    KeyCode::Esc => return Some(None),
+    KeyCode::Char('c') if ctrl => return Some(None),
+    KeyCode::Enter => return Some(app.selected().map(|hit| app.location(hit))),
+    KeyCode::Char('r') if ctrl => {
+      app.regex = !app.regex;
+      app.refresh();
+    },
+    KeyCode::Char('t') if ctrl => {
+      app.ignore_case = !app.ignore_case;
+      app.refresh();
+    },
+    KeyCode::Up => app.scroll(-1),
+    KeyCode::Down => app.scroll(1),
+    KeyCode::PageUp => app.scroll(-(PAGE_SIZE as isize)),
+    KeyCode::PageDown => app.scroll(PAGE_SIZE as isize),
+    KeyCode::Backspace => {
+      app.query.pop();
+      app.refresh();
+    },
+    KeyCode::Char(c) if !ctrl => {
+      app.query.push(c);
+      app.refresh();
+    },
+    _ => {},
+  }
+  None
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+  let [input_area, body_area, help_area] = Layout::vertical([
+    Constraint::Length(3),
+    Constraint::Min(0),
+    Constraint::Length(1),
+  ]).areas(frame.area());
+  let [results_area, preview_area] = Layout::horizontal([
+    Constraint::Percentage(50),
+    Constraint::Percentage(50),
+  ]).areas(body_area);
+
+  /* Query Input */
+  let mode = format!(
+    " {} | {} ",
+    if app.regex { "regex" } else { "literal" },
+    if app.ignore_case { "ignore case" } else { "match case" },
+  );
+  let input = Paragraph::new(app.query.as_str())
+    .block(Block::bordered().title(" Query ").title_bottom(mode));
+  frame.render_widget(input, input_area);
+  // Put the cursor at the end of the query (+1 for the border)
+  frame.set_cursor_position((
+    input_area.x + app.query.chars().count() as u16 + 1,
+    input_area.y + 1,
+  ));
+
+  /* Results */
+  let title = match &app.error {
+    Some(_) => String::from(" Invalid regex "),
+    None => format!(" {} results ", app.hits.len()),
+  };
+  let items: Vec<ListItem> = app.hits
+    .iter()
+    .map(|hit| ListItem::new(Line::from(vec![
+      Span::from(app.location(hit)).cyan(),
+      Span::from(" "),
+      Span::from(hit.line.trim()),
+    ])))
+    .collect();
+  let results = List::new(items)
+    .block(Block::bordered().title(title))
+    .highlight_style(Style::new().reversed());
+  frame.render_stateful_widget(results, results_area, &mut app.list);
+
+  /* Preview */
+  let preview = match (&app.error, app.selected()) {
+    (Some(error), _) => Paragraph::new(error.as_str()).red(),
+    (None, Some(hit)) => {
+      // Show as many lines around the hit as fit in the panel (-2 for the borders)
+      let context = preview_area.height.saturating_sub(2) as usize / 2;
+      let first = hit.line_number.saturating_sub(context).max(1);
+      let lines: Vec<Line> = app.files[hit.file].contents
+        .lines()
+        .enumerate()
+        .skip(first - 1)
+        .take(context * 2 + 1)
+        .map(|(i, line)| {
+          let line = Line::from(format!("{:>5} {}", i + 1, line));
+          if i + 1 == hit.line_number { line.yellow().bold() } else { line }
+        })
+        .collect();
+      Paragraph::new(lines)
+    },
+    (None, None) => Paragraph::new(""),
+  };
+  let preview_title = app.selected()
+    .map(|hit| format!(" {} ", app.files[hit.file].path.display()))
+    .unwrap_or_default();
+  frame.render_widget(preview.block(Block::bordered().title(preview_title)), preview_area);
+
+  /* Key Help */
+  let help = Paragraph::new(
+    "Enter: choose  Esc: quit  Up/Down/PgUp/PgDn: scroll  Ctrl-R: toggle regex  Ctrl-T: toggle ignore case"
+  ).dim();
+  frame.render_widget(help, help_area);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn app(contents: &str) -> App {
+    let mut app = App {
+      files: vec![LoadedFile { path: PathBuf::from("notes.txt"), contents: contents.to_string() }],
+      query: String::new(),
+      regex: false,
+      ignore_case: false,
+      hits: Vec::new(),
+      list: ListState::default(),
+      error: None,
+    };
+    app.refresh();
+    app
+  }
+
+  fn press(app: &mut App, code: KeyCode) -> Option<Option<String>> {
+    handle_key(app, KeyEvent::new(code, KeyModifiers::NONE))
+  }
+
+  fn ctrl(app: &mut App, c: char) -> Option<Option<String>> {
+    handle_key(app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL))
+  }
+
+  #[test]
+  fn typing_edits_the_query() {
+    let mut app = app("apple\nbanana\napricot");
+    for c in "apx".chars() {
+      press(&mut app, KeyCode::Char(c));
+    }
+    assert_eq!("apx", app.query);
+    assert!(app.hits.is_empty());
+
+    press(&mut app, KeyCode::Backspace);
+    assert_eq!(2, app.hits.len());
+    press(&mut app, KeyCode::Down);
+    assert_eq!(Some(Some(String::from("notes.txt:3"))), press(&mut app, KeyCode::Enter));
+  }
+
+  #[test]
+  fn ctrl_keys_toggle_options() {
+    let mut app = app("Apple\napple");
+    press(&mut app, KeyCode::Char('A'));
+    assert_eq!(1, app.hits.len());
+
+    ctrl(&mut app, 't');
+    assert!(app.ignore_case);
+    assert_eq!(2, app.hits.len());
+
+    // Half typed regexes show an error instead of results
+    ctrl(&mut app, 'r');
+    press(&mut app, KeyCode::Char('['));
+    assert!(app.regex);
+    assert!(app.error.is_some());
+    assert!(app.hits.is_empty());
+    press(&mut app, KeyCode::Backspace);
+    assert_eq!(None, app.error);
+    assert_eq!(2, app.hits.len());
+
+    // Ctrl-R and Ctrl-T aren't typed into the query
+    assert_eq!("A", app.query);
+  }
+
+  #[test]
+  fn escape_quits_without_a_choice() {
+    let mut app = app("apple");
+    press(&mut app, KeyCode::Char('a'));
+    assert_eq!(Some(None), press(&mut app, KeyCode::Esc));
+    assert_eq!(Some(None), ctrl(&mut app, 'c'));
+  }
+}
\ No newline at end of file
@@ -1,48 +1,113 @@
 use std::{
   error::Error,
   fs,
-  path::Path,
+  io::{Read, Seek, SeekFrom},
+  path::{Path, PathBuf},
   process,
+  time::Instant,
 };
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
+
+mod index;
+mod interactive;
+mod pre;
+mod results;
+mod stats;
+mod structured;
+mod watch;
 
 /// Self explanatory
 fn show_help_message() {
   println!("This is a simple grep clone written in Rust");
   println!("Usage: greprs [options] query filepath");
+  println!("       greprs index build directory");
+  println!("filepath can be a file or a directory, which is searched recursively");
   println!("Options:");
   println!("  -r, --regex\t\tUse a regex to search the file");
   println!("\t\t\tRegex should be wrapped in quotes so the shell doesn't interpret it (e.g. \"[a-z]+\\d+\")");
   println!("  -i, --ignore-case\tIgnore case when searching the file");
+  println!("  --interactive\t\tOpen a search UI for the file or directory at filepath");
+  println!("\t\t\tThe chosen match is printed as file:line on exit");
+  println!("  --watch\t\tKeep running and print new matching lines as they are written");
+  println!("  --index\t\tOnly search files that the directory's index says could match");
+  println!("\t\t\tThe index is created with `greprs index build directory`");
+  println!("  --csv-column NAME\tOnly match within the named column of a CSV file");
+  println!("  --json-path PATH\tOnly match the value at PATH (e.g. .user.name) of each JSON line");
+  println!("\t\t\tBoth print the whole matching record");
+  println!("  --pre COMMAND\t\tSearch the output of `COMMAND file` instead of the file itself");
+  println!("\t\t\tCOMMAND has to print the converted text, e.g. \"gunzip -c\" for .gz files");
+  println!("\t\t\tThe file's contents are also piped to COMMAND's stdin, for commands that read it");
+  println!("\t\t\tTools that write to a file (like pdftotext) need a wrapper script: pdftotext \"$1\" -");
+  println!("  --pre-glob GLOB\tOnly use --pre for files matching GLOB (e.g. \"*.pdf\"), can be repeated");
+  println!("  --save-results FILE\tSave the matches to FILE, to --compare against later");
+  println!("  --compare FILE\tOnly print matches that were added or removed since --save-results FILE");
+  println!("\t\t\tFiles are compared by their path inside filepath, so it can be typed differently");
+  println!("\t\t\tExits with an error if any were added");
+  println!("  --stats\t\tPrint a summary of what was searched, and how long it took, to stderr");
+  println!("  --json\t\tPrint the --stats summary as JSON");
   println!("  -h, --help\t\tDisplay this help message");
 }
 
+#[derive(Default)]
 pub struct Config {
   pub query: String,
   pub filepath: String,
   regex: bool,
   ignore_case: bool,
+  interactive: bool,
+  watch: bool,
+  index: bool,
+  build_index: bool,
+  csv_column: Option<String>,
+  json_path: Option<String>,
+  stats: bool,
+  json: bool,
+  pre: Option<String>,
+  pre_globs: Vec<String>,
+  save_results: Option<String>,
+  compare: Option<String>,
 }
 
 impl Config {
-  pub fn new(args: &Vec<String>) -> Result<Config, &'static str> {
+  pub fn new(args: &[String]) -> Result<Config, &'static str> {
     if args.len() == 1 {
       // We could show the error message here,
       // but the tutorial wants to explain error handling
       // with `unwrap_or_else`
       return Err("No arguments provided");
     }
+
+    // `greprs index build DIR` is the only subcommand
+    if args.len() == 4 && args[1] == "index" && args[2] == "build" {
+      return Ok(Config {
+        filepath: args[3].clone(),
+        build_index: true,
+        ..Default::default()
+      });
+    }
     
     let mut query: String = String::new();
     let mut filepath: String = String::new();
     let mut regex: bool = false;
     let mut ignore_case: bool = false;
+    let mut interactive: bool = false;
+    let mut watch: bool = false;
+    let mut index: bool = false;
+    let mut csv_column: Option<String> = None;
+    let mut json_path: Option<String> = None;
+    let mut stats: bool = false;
+    let mut json: bool = false;
+    let mut pre: Option<String> = None;
+    let mut pre_globs: Vec<String> = Vec::new();
+    let mut save_results: Option<String> = None;
+    let mut compare: Option<String> = None;
 
-    for i in 1..args.len() {
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
       // options generally come before the 
       // query and file path arguments
 
-      match args[i].as_str() {
+      match arg.as_str() {
         "-h" | "--help" => {
           show_help_message();
         },
@@ -52,32 +117,127 @@ impl Config {
         "-i" | "--ignore-case" => {
           ignore_case = true;
         },
+        "--interactive" => {
+          interactive = true;
+        },
+        "--watch" => {
+          watch = true;
+        },
+        "--index" => {
+          index = true;
+        },
+        "--csv-column" => {
+          csv_column = Some(args.next().ok_or("--csv-column needs a column name")?.clone());
+        },
+        "--json-path" => {
+          json_path = Some(args.next().ok_or("--json-path needs a path")?.clone());
+        },
+        "--stats" => {
+          stats = true;
+        },
+        "--json" => {
+          json = true;
+        },
+        "--pre" => {
+          pre = Some(args.next().ok_or("--pre needs a command")?.clone());
+        },
+        "--pre-glob" => {
+          let glob = args.next().ok_or("--pre-glob needs a glob")?;
+          if glob::Pattern::new(glob).is_err() {
+            return Err("--pre-glob is not a valid glob");
+          }
+          pre_globs.push(glob.clone());
+        },
+        "--save-results" => {
+          save_results = Some(args.next().ok_or("--save-results needs a file")?.clone());
+        },
+        "--compare" => {
+          compare = Some(args.next().ok_or("--compare needs a file")?.clone());
+        },
         _ => {
-          let path = Path::new(&args[i]);
+          let path = Path::new(arg);
           if path.exists() {
-            filepath = args[i].clone().to_string();
+            filepath = arg.clone();
           } else {
-            query = args[i].clone().to_string();
+            query = arg.clone();
           }
         },
       }
     }
 
+    if csv_column.is_some() && json_path.is_some() {
+      return Err("--csv-column and --json-path can't be used together");
+    }
+    if json && !stats {
+      return Err("--json requires --stats");
+    }
+
+    if watch && pre.is_some() {
+      // Converted output can't be followed a few lines at a time like the file itself
+      return Err("--watch and --pre can't be used together");
+    }
+    // Watch mode only prints new matching lines, it would quietly ignore all of these
+    let ignored_by_watch = [
+      (csv_column.is_some(), "--watch and --csv-column can't be used together"),
+      (json_path.is_some(), "--watch and --json-path can't be used together"),
+      (stats, "--watch and --stats can't be used together"),
+      (save_results.is_some(), "--watch and --save-results can't be used together"),
+      (compare.is_some(), "--watch and --compare can't be used together"),
+      (index, "--watch and --index can't be used together"),
+    ];
+    if let Some((_, err)) = ignored_by_watch.iter().find(|(used, _)| watch && *used) {
+      return Err(err);
+    }
+
+    if interactive && filepath.is_empty() {
+      // The query is typed in the UI, so only the path matters
+      filepath = String::from(".");
+    }
+
     // If help message was shown then exit
-    if query == "" && filepath == "" {
+    if query.is_empty() && filepath.is_empty() {
       process::exit(0);
     }
 
-    Ok(Config { query, filepath, regex, ignore_case })
+    Ok(Config {
+      query,
+      filepath,
+      regex,
+      ignore_case,
+      interactive,
+      watch,
+      index,
+      build_index: false,
+      csv_column,
+      json_path,
+      stats,
+      json,
+      pre,
+      pre_globs,
+      save_results,
+      compare,
+    })
   }
 }
 
-fn regex_search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+/// Compiles the query as a regex. Every regex search goes through here,
+/// so `--ignore-case` means the same thing on the command line, in `--watch`,
+/// in `--stats` and in the interactive UI.
+fn build_regex(query: &str, ignore_case: bool) -> Result<Regex, regex::Error> {
+  RegexBuilder::new(query).case_insensitive(ignore_case).build()
+}
+
+fn regex_search<'a>(query: &str, contents: &'a str, ignore_case: bool) -> Vec<&'a str> {
+  without_line_numbers(regex_search_numbered(query, contents, ignore_case))
+}
+
+/// Same as `regex_search`, but keeps the line number (starting at 1) of each match
+fn regex_search_numbered<'a>(query: &str, contents: &'a str, ignore_case: bool) -> Vec<(usize, &'a str)> {
   let mut results = Vec::new();
-  let re = Regex::new(query).unwrap();
-  for line in contents.lines() {
+  let re = build_regex(query, ignore_case).unwrap();
+  for (i, line) in contents.lines().enumerate() {
     if re.is_match(line) {
-      results.push(line);
+      results.push((i + 1, line));
     }
   }
   results
@@ -89,62 +249,265 @@ fn search<'a>(
   contents: &'a str,
   ignore_case: bool,
 ) -> Vec<&'a str> {
+  without_line_numbers(search_numbered(query, contents, ignore_case))
+}
+
+/// Same as `search`, but keeps the line number (starting at 1) of each match
+fn search_numbered<'a>(
+  query: &str,
+  contents: &'a str,
+  ignore_case: bool,
+) -> Vec<(usize, &'a str)> {
   let query = if ignore_case { 
-    query.to_lowercase() 
+    lowercase(query) 
   } else { 
     query.to_string() 
   };
   let mut results = Vec::new();
-  for line in contents.lines() {
+  for (i, line) in contents.lines().enumerate() {
     let check = if ignore_case { 
-      line.to_lowercase() 
+      lowercase(line) 
     } else { 
       line.to_string()
     };
     if check.contains(&query) {
-      results.push(line);
+      results.push((i + 1, line));
     }
   }
   results
 }
 
+/// Searches `contents` the way the command line options ask for
+fn matching_lines<'a>(config: &Config, contents: &'a str) -> Vec<&'a str> {
+  if config.regex {
+    regex_search(config.query.as_str(), contents, config.ignore_case)
+  } else {
+    search(config.query.as_str(), contents, config.ignore_case)
+  }
+}
+
+/// Like `matching_lines`, but when a CSV column or JSON path was given
+/// only that field is searched and the whole record is returned
+fn matching_records<'a>(config: &Config, contents: &'a str) -> Result<Vec<&'a str>, Box<dyn Error>> {
+  if let Some(column) = &config.csv_column {
+    structured::csv_search(config, column, contents)
+  } else if let Some(path) = &config.json_path {
+    structured::json_search(config, path, contents)
+  } else {
+    Ok(matching_lines(config, contents))
+  }
+}
+
+/// Lowercases one character at a time. `str::to_lowercase` turns a
+/// word-final Σ into ς instead of σ, so "AΣ" wouldn't contain "Σ" anymore.
+fn lowercase(text: &str) -> String {
+  text.chars().flat_map(char::to_lowercase).collect()
+}
+
+/// Picks between `search` and `regex_search` and keeps the line numbers
+fn find_lines<'a>(
+  query: &str,
+  contents: &'a str,
+  regex: bool,
+  ignore_case: bool,
+) -> Vec<(usize, &'a str)> {
+  if regex {
+    regex_search_numbered(query, contents, ignore_case)
+  } else {
+    search_numbered(query, contents, ignore_case)
+  }
+}
+
+fn without_line_numbers(results: Vec<(usize, &str)>) -> Vec<&str> {
+  results.into_iter().map(|(_, line)| line).collect()
+}
+
 /// Reads the contents of a file
-fn read_file_contents(filepath: &String) -> Result<String, Box<dyn Error>> {
+fn read_file_contents<P: AsRef<Path>>(filepath: P) -> Result<String, Box<dyn Error>> {
   let contents = fs::read_to_string(filepath)?;
 
   Ok(contents)
 }
 
+/// Reads the complete lines of a file, starting at byte `offset`.
+/// Also returns the offset just after the last complete line, so that
+/// a line which is still being written gets picked up by the next read.
+fn read_new_lines<P: AsRef<Path>>(filepath: P, offset: u64) -> Result<(String, u64), Box<dyn Error>> {
+  let mut file = fs::File::open(filepath)?;
+  file.seek(SeekFrom::Start(offset))?;
+  let mut bytes = Vec::new();
+  file.read_to_end(&mut bytes)?;
+
+  let complete = bytes
+    .iter()
+    .rposition(|&byte| byte == b'\n')
+    .map(|i| i + 1)
+    .unwrap_or(0);
+  bytes.truncate(complete);
+  let contents = String::from_utf8(bytes)?;
+
+  Ok((contents, offset + complete as u64))
+}
+
+/// Reads a file to be searched, running it through the `--pre` command first if it applies
+fn read_file<P: AsRef<Path>>(config: &Config, filepath: P) -> Result<String, Box<dyn Error>> {
+  if pre::applies(config, filepath.as_ref()) {
+    pre::convert(config, filepath.as_ref())
+  } else {
+    read_file_contents(filepath)
+  }
+}
+
+/// Returns `path` if it is a file, otherwise every file below it.
+/// Hidden entries (starting with `.`) and symlinked directories are skipped.
+fn collect_files(path: &Path) -> Vec<PathBuf> {
+  if !path.is_dir() {
+    return vec![path.to_path_buf()];
+  }
+
+  let mut entries: Vec<PathBuf> = match fs::read_dir(path) {
+    Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+    Err(_) => return Vec::new(),
+  };
+  // read_dir doesn't guarantee any order
+  entries.sort();
+
+  let mut files = Vec::new();
+  for entry in entries {
+    let hidden = entry
+      .file_name()
+      .map(|name| name.to_string_lossy().starts_with('.'))
+      .unwrap_or(false);
+    if hidden || (entry.is_symlink() && entry.is_dir()) {
+      continue;
+    }
+    files.extend(collect_files(&entry));
+  }
+  files
+}
+
 /// Handles running the logic of the program
 pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
   // The Box<dyn Error> type is a trait object which allows us
   // not to specify the exact type of the error
   // The `dyn` keyword is short for dynamic
-  let contents = read_file_contents(&config.filepath)
-    .expect("Something went wrong reading the file");
+  if config.interactive {
+    if let Some(choice) = interactive::run(&config)? {
+      println!("{}", choice);
+    }
+    return Ok(());
+  }
+  if config.watch {
+    return watch::run(&config);
+  }
+  if config.build_index {
+    return index::build(&config);
+  }
 
-  if config.regex {
-    for line in regex_search(
-      config.query.as_str(), 
-      contents.as_str(),
-    ) {
-      println!("{}", line);
+  let start = Instant::now();
+  let mut stats = stats::Stats::default();
+  // Everything that matched, for --save-results and --compare
+  let mut found = Vec::new();
+
+  let path = Path::new(&config.filepath);
+  if path.is_dir() {
+    // The index has the raw text, but CSV and JSON fields are matched once
+    // they're decoded (e.g. "caf\u00e9" is "café"), so it can't narrow those down
+    let structured = config.csv_column.is_some() || config.json_path.is_some();
+    let files = if config.index && !structured {
+      let mut index = index::Index::load(path)?
+        .ok_or("No index found, create one with `greprs index build directory`")?;
+      // Files changed since the index was built would otherwise be missed
+      if index.update()? > 0 {
+        if let Err(e) = index.save() {
+          eprintln!("Couldn't update the index: {}", e);
+        }
+      }
+      let mut files = index.candidates(&config.query, config.regex);
+      // The index only knows what the files look like before --pre converts them
+      for file in collect_files(path) {
+        if pre::applies(&config, &file) && !files.contains(&file) {
+          files.push(file);
+        }
+      }
+      files.sort();
+      files
+    } else {
+      collect_files(path)
+    };
+    // A baseline saved inside the searched directory would match itself
+    let baselines: Vec<PathBuf> = [&config.save_results, &config.compare]
+      .into_iter()
+      .flatten()
+      .filter_map(|baseline| fs::canonicalize(baseline).ok())
+      .collect();
+    let files = files
+      .into_iter()
+      .filter(|file| fs::canonicalize(file).map(|file| !baselines.contains(&file)).unwrap_or(true));
+
+    for file in files {
+      // Anything that isn't UTF-8 text (e.g. binaries) is skipped
+      let contents = match read_file(&config, &file) {
+        Ok(contents) => contents,
+        Err(e) => {
+          stats.skip(e.as_ref());
+          continue;
+        },
+      };
+      // e.g. files without the CSV column, when searching a mixed directory
+      let records = match matching_records(&config, &contents) {
+        Ok(records) => records,
+        Err(e) => {
+          stats.skip(e.as_ref());
+          continue;
+        },
+      };
+      stats.searched(&config, &contents, &records);
+      for line in records {
+        if config.compare.is_none() {
+          println!("{}:{}", file.display(), line);
+        }
+        found.push(results::Found::new(&results::relative_path(path, &file), line));
+      }
     }
   } else {
-    for line in search(
-      config.query.as_str(), 
-      contents.as_str(),
-      config.ignore_case,
-    ) {
-      println!("{}", line);
+    // e.g. a --pre command that doesn't exist, there's nothing else to search
+    let contents = read_file(&config, &config.filepath)
+      .map_err(|e| format!("Couldn't read {}: {}", config.filepath, e))?;
+
+    let records = matching_records(&config, &contents)?;
+    stats.searched(&config, &contents, &records);
+    for line in records {
+      if config.compare.is_none() {
+        println!("{}", line);
+      }
+      found.push(results::Found::new(&results::relative_path(path, path), line));
     }
   }
+
+  let added = match &config.compare {
+    Some(baseline) => results::compare(baseline, &found)?,
+    None => 0,
+  };
+  if let Some(filepath) = &config.save_results {
+    results::save(filepath, &found)?;
+  }
+
+  if config.stats {
+    stats.elapsed = start.elapsed();
+    stats.print(config.json);
+  }
+
+  if added > 0 {
+    return Err(format!("{} new matches since {}", added, config.compare.unwrap_or_default()).into());
+  }
   Ok(())
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use proptest::prelude::*;
 
   #[test]
   #[should_panic]
@@ -153,6 +516,42 @@ mod tests {
     read_file_contents(&filepath).unwrap();
   }
 
+  #[test]
+  fn watch_rejects_options_it_would_ignore() {
+    let args = |extra: &[&str]| -> Vec<String> {
+      ["greprs", "--watch"].iter().chain(extra).map(|arg| arg.to_string()).collect()
+    };
+    assert!(Config::new(&args(&["error", "."])).is_ok());
+    for extra in [
+      &["--json-path", ".level"][..],
+      &["--csv-column", "level"],
+      &["--stats"],
+      &["--save-results", "out.json"],
+      &["--compare", "out.json"],
+      &["--index"],
+    ] {
+      let args = args(&[extra, &["error", "."]].concat());
+      assert!(Config::new(&args).is_err(), "{:?} was allowed", args);
+    }
+  }
+
+  #[test]
+  fn read_only_complete_lines() {
+    let filepath = std::env::temp_dir().join("greprs_read_new_lines.txt");
+    fs::write(&filepath, "first\nsecond\nthi").unwrap();
+
+    let (contents, offset) = read_new_lines(&filepath, 0).unwrap();
+    assert_eq!("first\nsecond\n", contents);
+    assert_eq!(13, offset);
+
+    fs::write(&filepath, "first\nsecond\nthird\n").unwrap();
+    let (contents, offset) = read_new_lines(&filepath, offset).unwrap();
+    assert_eq!("third\n", contents);
+    assert_eq!(19, offset);
+
+    fs::remove_file(&filepath).unwrap();
+  }
+
   #[test]
   fn one_result() {
     let query = "duct";
@@ -196,6 +595,20 @@ Pick three.";
     );
   }
 
+  #[test]
+  fn case_insensitive_final_sigma() {
+    // A Σ at the end of a word lowercases to ς with `str::to_lowercase`,
+    // but it's still the same letter as any other Σ
+    let contents = "\
+ΟΔΟΣ
+ΣΟΦΙΑ";
+
+    assert_eq!(
+      vec!["ΟΔΟΣ", "ΣΟΦΙΑ"],
+      search("Σ", contents, true)
+    );
+  }
+
   #[test]
   fn search_with_regex() {
     let query = "hello[0-9]";
@@ -206,7 +619,53 @@ helloABC";
 
     assert_eq!(
       vec!["hello1", "hello2"],
-      regex_search(query, contents)
+      regex_search(query, contents, false)
     );
   }
+
+  #[test]
+  fn regex_ignore_case_everywhere() {
+    let config = Config { query: String::from("hel+o"), regex: true, ignore_case: true, ..Default::default() };
+    let contents = "HELLO
+hello
+help";
+
+    assert_eq!(vec!["HELLO", "hello"], matching_lines(&config, contents));
+    assert_eq!(vec![(1, "HELLO"), (2, "hello")], find_lines(&config.query, contents, true, true));
+  }
+
+  // Short queries over a small alphabet, so that matches actually happen
+  fn query() -> impl Strategy<Value = String> {
+    "[a-cA-C .*()\\[\\]ΣσςéÉ]{0,3}"
+  }
+
+  fn contents() -> impl Strategy<Value = String> {
+    "[a-cA-C .*()\\[\\]ΣσςéÉ\n]{0,60}"
+  }
+
+  proptest! {
+    #[test]
+    fn literal_and_escaped_regex_agree(query in query(), contents in contents()) {
+      prop_assert_eq!(
+        search(&query, &contents, false),
+        regex_search(&regex::escape(&query), &contents, false)
+      );
+    }
+
+    #[test]
+    fn ignore_case_finds_everything_case_sensitive_does(query in query(), contents in contents()) {
+      let sensitive = search(&query, &contents, false);
+      let insensitive = search(&query, &contents, true);
+
+      // Both keep the file's line order, so the case-sensitive
+      // results have to appear, in order, within the other ones
+      let mut remaining = insensitive.iter();
+      for line in &sensitive {
+        prop_assert!(
+          remaining.any(|other| other == line),
+          "{:?} was found case-sensitively but not with ignore case", line
+        );
+      }
+    }
+  }
 }
\ No newline at end of file
@@ -0,0 +1,113 @@
+use std::{
+  error::Error,
+  fs,
+  io::Write,
+  path::Path,
+  process::{Command, Stdio},
+  thread,
+};
+use glob::Pattern;
+
+use crate::Config;
+
+/// Whether `--pre` should be used for this file.
+/// With no `--pre-glob`, every file goes through it.
+pub fn applies(config: &Config, filepath: &Path) -> bool {
+  if config.pre.is_none() {
+    return false;
+  }
+  if config.pre_globs.is_empty() {
+    return true;
+  }
+
+  // "*.pdf" should match "docs/a.pdf", so try the file name on its own too
+  let name = filepath.file_name().map(Path::new);
+  config.pre_globs.iter().any(|glob| {
+    // Globs were checked when parsing the arguments
+    let pattern = Pattern::new(glob).unwrap();
+    pattern.matches_path(filepath) || name.map(|name| pattern.matches_path(name)).unwrap_or(false)
+  })
+}
+
+/// Runs the `--pre` command on a file and returns what it printed.
+///
+/// The command is called as `COMMAND filepath`, with the file's contents
+/// piped to its stdin, so it can use whichever of the two it prefers.
+pub fn convert(config: &Config, filepath: &Path) -> Result<String, Box<dyn Error>> {
+  let command = config.pre.as_deref().ok_or("No --pre command given")?;
+  // Allows e.g. `--pre "gunzip -c"`
+  let mut parts = command.split_whitespace();
+  let program = parts.next().ok_or("--pre command is empty")?;
+
+  let contents = fs::read(filepath)?;
+  let mut child = Command::new(program)
+    .args(parts)
+    .arg(filepath)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .map_err(|e| format!("Couldn't run `{}`: {}", command, e))?;
+
+  // Write from another thread so a big file can't fill up the pipe
+  // while the command is blocked writing its own output
+  let mut stdin = child.stdin.take().ok_or("Couldn't open the --pre command's stdin")?;
+  let writer = thread::spawn(move || {
+    // Commands that only read the file by its path close stdin early,
+    // so a failed write here isn't an error
+    let _ = stdin.write_all(&contents);
+  });
+
+  let output = child.wait_with_output()?;
+  let _ = writer.join();
+
+  if !output.status.success() {
+    return Err(format!(
+      "`{}` failed on {}: {}",
+      command,
+      filepath.display(),
+      String::from_utf8_lossy(&output.stderr).trim(),
+    ).into());
+  }
+
+  // Converters don't always produce clean UTF-8, but what they do produce is still worth searching
+  Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config(pre: &str, pre_globs: &[&str]) -> Config {
+    Config {
+      pre: Some(pre.to_string()),
+      pre_globs: pre_globs.iter().map(|glob| glob.to_string()).collect(),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn globs_match_file_names() {
+    let config = config("cat", &["*.pdf"]);
+    assert!(applies(&config, Path::new("docs/manual.pdf")));
+    assert!(!applies(&config, Path::new("docs/manual.txt")));
+  }
+
+  #[test]
+  fn convert_pipes_file_and_passes_path() {
+    let filepath = std::env::temp_dir().join("greprs_pre_convert.txt");
+    fs::write(&filepath, "hello\n").unwrap();
+
+    // `cat -` prints its stdin first, then the file named by the argument
+    let result = convert(&config("cat -", &[]), &filepath);
+    fs::remove_file(&filepath).unwrap();
+
+    assert_eq!("hello\nhello\n", result.unwrap());
+  }
+
+  #[test]
+  fn convert_fails_with_command() {
+    let result = convert(&config("false", &[]), Path::new("Cargo.toml"));
+    assert!(result.is_err());
+  }
+}
@@ -0,0 +1,123 @@
+use std::{
+  collections::HashMap,
+  error::Error,
+  fs,
+  path::{Component, Path},
+};
+use serde::{Deserialize, Serialize};
+
+/// A match, identified by its file and the content of the line.
+/// The line number is left out on purpose, so that editing code above
+/// an old match doesn't make it look like a new one.
+/// The file is relative to the searched directory (see `relative_path`).
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct Found {
+  file: String,
+  line: String,
+}
+
+impl Found {
+  pub fn new(file: &str, line: &str) -> Found {
+    Found { file: file.to_string(), line: line.to_string() }
+  }
+}
+
+/// Where `file` is below the searched `root`, the same no matter how the root was
+/// typed (`src`, `./src` or `src/`), with `/` between directories on every OS.
+/// When a single file was searched, that's just its name.
+pub fn relative_path(root: &Path, file: &Path) -> String {
+  let relative = match file.strip_prefix(root) {
+    Ok(relative) if relative.as_os_str().is_empty() => file.file_name().map(Path::new).unwrap_or(file),
+    Ok(relative) => relative,
+    Err(_) => file,
+  };
+  let parts: Vec<String> = relative
+    .components()
+    .filter_map(|part| match part {
+      Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+      _ => None,
+    })
+    .collect();
+  parts.join("/")
+}
+
+pub fn save(filepath: &str, found: &[Found]) -> Result<(), Box<dyn Error>> {
+  fs::write(filepath, serde_json::to_string_pretty(found)?)?;
+  Ok(())
+}
+
+/// Prints the matches that were added (`+`) or removed (`-`) compared
+/// to the results saved in `baseline`. Returns how many were added.
+pub fn compare(baseline: &str, found: &[Found]) -> Result<usize, Box<dyn Error>> {
+  let contents = fs::read_to_string(baseline)
+    .map_err(|e| format!("Couldn't read {}: {}", baseline, e))?;
+  let saved: Vec<Found> = serde_json::from_str(&contents)?;
+
+  let (added, removed) = diff(&saved, found);
+  for hit in &added {
+    println!("+ {}:{}", hit.file, hit.line);
+  }
+  for hit in &removed {
+    println!("- {}:{}", hit.file, hit.line);
+  }
+  Ok(added.len())
+}
+
+/// Returns (added, removed), both sorted.
+/// The same line can show up more than once in a file, so
+/// each copy in `saved` only cancels out one copy in `found`.
+fn diff(saved: &[Found], found: &[Found]) -> (Vec<Found>, Vec<Found>) {
+  let mut remaining: HashMap<&Found, usize> = HashMap::new();
+  for hit in saved {
+    *remaining.entry(hit).or_insert(0) += 1;
+  }
+
+  let mut added = Vec::new();
+  for hit in found {
+    match remaining.get_mut(hit) {
+      Some(count) if *count > 0 => *count -= 1,
+      _ => added.push(hit.clone()),
+    }
+  }
+
+  let mut removed: Vec<Found> = remaining
+    .into_iter()
+    .flat_map(|(hit, count)| std::iter::repeat_n(hit.clone(), count))
+    .collect();
+
+  added.sort();
+  removed.sort();
+  (added, removed)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn diff_added_and_removed() {
+    let saved = vec![
+      Found::new("a.rs", "// TODO: one"),
+      Found::new("a.rs", "// TODO: two"),
+      Found::new("b.rs", "// TODO: one"),
+    ];
+    let found = vec![
+      Found::new("a.rs", "// TODO: one"),
+      Found::new("b.rs", "// TODO: one"),
+      Found::new("b.rs", "// TODO: one"),
+    ];
+
+    let (added, removed) = diff(&saved, &found);
+    assert_eq!(vec![Found::new("b.rs", "// TODO: one")], added);
+    assert_eq!(vec![Found::new("a.rs", "// TODO: two")], removed);
+  }
+
+  #[test]
+  fn paths_are_relative_to_the_root() {
+    for root in ["src", "./src", "src/"] {
+      let file = Path::new(root).join("bin").join("main.rs");
+      assert_eq!("bin/main.rs", relative_path(Path::new(root), &file));
+    }
+    assert_eq!("main.rs", relative_path(Path::new("./src/main.rs"), Path::new("./src/main.rs")));
+  }
+}
@@ -0,0 +1,121 @@
+use std::{
+  error::Error,
+  io::{self, ErrorKind},
+  time::Duration,
+};
+use serde_json::json;
+
+use crate::{build_regex, lowercase, Config};
+
+/// Totals for everything `run` searched, printed with `--stats`
+#[derive(Default)]
+pub struct Stats {
+  files_searched: usize,
+  bytes_scanned: u64,
+  lines_matched: usize,
+  matches_found: usize,
+  skipped_binary: usize,
+  skipped_permission: usize,
+  // Anything else that stopped a file from being searched
+  skipped_other: usize,
+  pub elapsed: Duration,
+}
+
+impl Stats {
+  /// Counts a file that was searched, given the records that matched in it
+  pub fn searched(&mut self, config: &Config, contents: &str, records: &[&str]) {
+    self.files_searched += 1;
+    self.bytes_scanned += contents.len() as u64;
+    self.lines_matched += records.len();
+    self.matches_found += count_matches(config, records);
+  }
+
+  /// Counts a file that couldn't be searched, sorted by why
+  pub fn skip(&mut self, error: &(dyn Error + 'static)) {
+    match error.downcast_ref::<io::Error>().map(|e| e.kind()) {
+      // `read_to_string` fails with InvalidData when the file isn't UTF-8
+      Some(ErrorKind::InvalidData) => self.skipped_binary += 1,
+      Some(ErrorKind::PermissionDenied) => self.skipped_permission += 1,
+      _ => self.skipped_other += 1,
+    }
+  }
+
+  /// Goes to stderr, so it doesn't get mixed up with the matches on stdout
+  pub fn print(&self, json: bool) {
+    if json {
+      let summary = json!({
+        "files_searched": self.files_searched,
+        "bytes_scanned": self.bytes_scanned,
+        "lines_matched": self.lines_matched,
+        "matches_found": self.matches_found,
+        "files_skipped": {
+          "binary": self.skipped_binary,
+          "permission": self.skipped_permission,
+          "other": self.skipped_other,
+        },
+        "elapsed_seconds": self.elapsed.as_secs_f64(),
+      });
+      eprintln!("{}", summary);
+      return;
+    }
+
+    eprintln!();
+    eprintln!("{} files searched", self.files_searched);
+    eprintln!("{} bytes scanned", self.bytes_scanned);
+    eprintln!("{} lines matched", self.lines_matched);
+    eprintln!("{} matches found", self.matches_found);
+    eprintln!(
+      "{} files skipped ({} binary, {} permission denied, {} other)",
+      self.skipped_binary + self.skipped_permission + self.skipped_other,
+      self.skipped_binary,
+      self.skipped_permission,
+      self.skipped_other,
+    );
+    eprintln!("{:.6} seconds", self.elapsed.as_secs_f64());
+  }
+}
+
+/// How many times the query shows up in the matching lines.
+/// A CSV or JSON record counts once, since only one field was searched.
+fn count_matches(config: &Config, lines: &[&str]) -> usize {
+  if config.csv_column.is_some() || config.json_path.is_some() {
+    return lines.len();
+  }
+
+  if config.regex {
+    // These lines already matched, so the regex is valid
+    let re = build_regex(&config.query, config.ignore_case).unwrap();
+    return lines.iter().map(|line| re.find_iter(line).count()).sum();
+  }
+
+  if config.ignore_case {
+    let query = lowercase(&config.query);
+    lines.iter().map(|line| lowercase(line).matches(&query).count()).sum()
+  } else {
+    lines.iter().map(|line| line.matches(&config.query).count()).sum()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn counts_every_match_on_a_line() {
+    let config = Config { query: String::from("ab"), ignore_case: true, ..Default::default() };
+    assert_eq!(3, count_matches(&config, &["ab AB", "xaby"]));
+
+    let config = Config { query: String::from("a+b"), regex: true, ..config };
+    assert_eq!(3, count_matches(&config, &["ab AAB", "xaby"]));
+  }
+
+  #[test]
+  fn sorts_skipped_files() {
+    let mut stats = Stats::default();
+    stats.skip(&io::Error::from(ErrorKind::InvalidData));
+    stats.skip(&io::Error::from(ErrorKind::PermissionDenied));
+    stats.skip(&io::Error::from(ErrorKind::NotFound));
+
+    assert_eq!((1, 1, 1), (stats.skipped_binary, stats.skipped_permission, stats.skipped_other));
+  }
+}
@@ -0,0 +1,137 @@
+use std::error::Error;
+use serde_json::Value;
+
+use crate::{matching_lines, Config};
+
+/// Searches a single column of CSV `contents`, found by its name in the header row.
+/// Returns the full text of each record whose column matches.
+pub fn csv_search<'a>(
+  config: &Config,
+  column: &str,
+  contents: &'a str,
+) -> Result<Vec<&'a str>, Box<dyn Error>> {
+  let mut reader = csv::Reader::from_reader(contents.as_bytes());
+  let column = reader
+    .headers()?
+    .iter()
+    .position(|header| header == column)
+    .ok_or_else(|| format!("No CSV column named {}", column))?;
+
+  // Keep where each record starts so the original text can be printed,
+  // quoting and all, instead of how the csv crate would write it back out
+  let mut records = Vec::new();
+  for record in reader.records() {
+    let record = record?;
+    let start = record.position().map(|position| position.byte()).unwrap_or(0) as usize;
+    let field = record.get(column).unwrap_or("");
+    records.push((start, !matching_lines(config, field).is_empty()));
+  }
+
+  let mut results = Vec::new();
+  for (i, (start, matched)) in records.iter().enumerate() {
+    if !matched {
+      continue;
+    }
+    // A record ends where the next one starts
+    let end = records.get(i + 1).map(|(next, _)| *next).unwrap_or(contents.len());
+    results.push(contents[*start..end].trim_end_matches(['\r', '\n']));
+  }
+  Ok(results)
+}
+
+/// Searches the value at `path` (e.g. `.user.name`, or `.items.0` for arrays)
+/// in each line of JSON Lines `contents`. Returns the lines whose value matches.
+/// Lines that aren't JSON, or don't have anything at `path`, are skipped.
+pub fn json_search<'a>(
+  config: &Config,
+  path: &str,
+  contents: &'a str,
+) -> Result<Vec<&'a str>, Box<dyn Error>> {
+  if !path.starts_with('.') {
+    return Err(format!("JSON paths start with a `.`, e.g. .{}", path).into());
+  }
+  let keys: Vec<&str> = path.split('.').skip(1).filter(|key| !key.is_empty()).collect();
+
+  let mut results = Vec::new();
+  for line in contents.lines() {
+    let Ok(record) = serde_json::from_str::<Value>(line) else {
+      continue;
+    };
+    let Some(value) = lookup(&record, &keys) else {
+      continue;
+    };
+
+    // Strings are searched without their quotes,
+    // anything else (numbers, objects...) as JSON
+    let text = match value {
+      Value::String(text) => text.clone(),
+      other => other.to_string(),
+    };
+    if !matching_lines(config, &text).is_empty() {
+      results.push(line);
+    }
+  }
+  Ok(results)
+}
+
+fn lookup<'v>(value: &'v Value, keys: &[&str]) -> Option<&'v Value> {
+  keys.iter().try_fold(value, |value, key| match value {
+    Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+    _ => value.get(key),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config(query: &str) -> Config {
+    Config { query: query.to_string(), ..Default::default() }
+  }
+
+  #[test]
+  fn csv_only_matches_column() {
+    let contents = "\
+name,city
+Alice,Paris
+\"Paris, Bob\",London
+Carol,Paris";
+
+    assert_eq!(
+      vec!["Alice,Paris", "Carol,Paris"],
+      csv_search(&config("Paris"), "city", contents).unwrap()
+    );
+  }
+
+  #[test]
+  fn csv_missing_column() {
+    assert!(csv_search(&config("Paris"), "country", "name,city\nAlice,Paris").is_err());
+  }
+
+  #[test]
+  fn json_only_matches_path() {
+    let contents = r#"{"user": {"name": "error"}, "msg": "ok"}
+{"user": {"name": "bob"}, "msg": "error"}
+not json at all, error
+{"user": {"name": "error bob"}}"#;
+
+    assert_eq!(
+      vec![
+        r#"{"user": {"name": "error"}, "msg": "ok"}"#,
+        r#"{"user": {"name": "error bob"}}"#,
+      ],
+      json_search(&config("error"), ".user.name", contents).unwrap()
+    );
+  }
+
+  #[test]
+  fn json_path_into_array() {
+    let contents = r#"{"tags": ["a", "b"]}
+{"tags": ["b", "a"]}"#;
+
+    assert_eq!(
+      vec![r#"{"tags": ["b", "a"]}"#],
+      json_search(&config("b"), ".tags.0", contents).unwrap()
+    );
+  }
+}
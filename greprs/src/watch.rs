@@ -0,0 +1,248 @@
+use std::{
+  collections::HashMap,
+  error::Error,
+  fs,
+  path::{Path, PathBuf},
+  sync::mpsc,
+};
+use notify::{
+  event::{ModifyKind, RenameMode},
+  Event,
+  EventKind,
+  RecursiveMode,
+  Watcher,
+};
+
+use crate::{collect_files, matching_lines, read_new_lines, Config};
+
+/// Keeps track of how far into each watched file we have already searched
+struct Tail {
+  // Canonical path that was passed on the command line
+  root: PathBuf,
+  // What the user typed, used to print paths the same way `run` does
+  display_root: PathBuf,
+  // false when watching a single file
+  is_dir: bool,
+  offsets: HashMap<PathBuf, u64>,
+}
+
+impl Tail {
+  fn new(config: &Config) -> Result<Tail, Box<dyn Error>> {
+    let display_root = PathBuf::from(&config.filepath);
+    let root = fs::canonicalize(&display_root)?;
+    let is_dir = root.is_dir();
+
+    // Only lines written from now on are of interest,
+    // so start at the end of everything that already exists
+    let mut offsets = HashMap::new();
+    for file in collect_files(&root) {
+      if let Ok(metadata) = fs::metadata(&file) {
+        offsets.insert(file, metadata.len());
+      }
+    }
+
+    Ok(Tail { root, display_root, is_dir, offsets })
+  }
+
+  /// Whether an event for `path` is something we were asked to watch
+  fn is_watched(&self, path: &Path) -> bool {
+    if !self.is_dir {
+      return path == self.root;
+    }
+    // Same rules as `collect_files`: nothing hidden
+    match path.strip_prefix(&self.root) {
+      Ok(relative) => !relative
+        .components()
+        .any(|part| part.as_os_str().to_string_lossy().starts_with('.')),
+      Err(_) => false,
+    }
+  }
+
+  /// Returns the lines to print for everything new that `event` turned up
+  fn handle(&mut self, config: &Config, event: Event) -> Vec<String> {
+    let mut output = Vec::new();
+    for (i, path) in event.paths.iter().enumerate() {
+      if !self.is_watched(path) {
+        continue;
+      }
+
+      match event.kind {
+        // Deleted, or renamed away by log rotation. Whatever shows up
+        // under this name next is a new file and is read from the start.
+        EventKind::Remove(_)
+        | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+          self.offsets.remove(path);
+        },
+        // A file moved in under a new name (e.g. `app.log` -> `app.log.1`).
+        // Its contents have already been seen (or are old), so skip them.
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+          self.skip_to_end(path);
+        },
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+          // paths are [from, to]
+          if i == 0 {
+            self.offsets.remove(path);
+          } else {
+            self.skip_to_end(path);
+          }
+        },
+        EventKind::Modify(ModifyKind::Name(_)) => {
+          if path.is_file() {
+            self.skip_to_end(path);
+          } else {
+            self.offsets.remove(path);
+          }
+        },
+        EventKind::Create(_) | EventKind::Modify(_) => {
+          output.extend(self.new_matches(config, path));
+        },
+        _ => {},
+      }
+    }
+    output
+  }
+
+  fn skip_to_end(&mut self, path: &Path) {
+    if let Ok(metadata) = fs::metadata(path) {
+      self.offsets.insert(path.to_path_buf(), metadata.len());
+    }
+  }
+
+  /// Searches everything written to `path` since the last time we looked
+  fn new_matches(&mut self, config: &Config, path: &Path) -> Vec<String> {
+    let Ok(metadata) = fs::metadata(path) else {
+      // Already gone again
+      self.offsets.remove(path);
+      return Vec::new();
+    };
+    if !metadata.is_file() {
+      return Vec::new();
+    }
+
+    // A file we haven't seen yet is new, so all of it is new.
+    // If it got smaller it was truncated (e.g. copytruncate log rotation).
+    let mut offset = self.offsets.get(path).copied().unwrap_or(0);
+    if metadata.len() < offset {
+      offset = 0;
+    }
+
+    let (contents, offset) = match read_new_lines(path, offset) {
+      Ok(read) => read,
+      // Not UTF-8 text, don't try it again until it changes size
+      Err(_) => (String::new(), metadata.len()),
+    };
+    self.offsets.insert(path.to_path_buf(), offset);
+
+    matching_lines(config, &contents)
+      .into_iter()
+      .map(|line| {
+        if self.is_dir {
+          let relative = path.strip_prefix(&self.root).unwrap_or(path);
+          format!("{}:{}", self.display_root.join(relative).display(), line)
+        } else {
+          line.to_string()
+        }
+      })
+      .collect()
+  }
+}
+
+/// Watches the file or directory from the config and prints
+/// matching lines as they get written, until the process is killed.
+pub fn run(config: &Config) -> Result<(), Box<dyn Error>> {
+  let mut tail = Tail::new(config)?;
+
+  let (tx, rx) = mpsc::channel();
+  let mut watcher = notify::recommended_watcher(tx)?;
+  if tail.is_dir {
+    watcher.watch(&tail.root, RecursiveMode::Recursive)?;
+  } else {
+    // Watching the file itself would lose track of it as soon as it's
+    // rotated, so watch the directory it lives in and filter events instead
+    let parent = tail.root.parent().ok_or("Can't watch the root directory")?;
+    watcher.watch(parent, RecursiveMode::NonRecursive)?;
+  }
+
+  for event in rx {
+    for line in tail.handle(config, event?) {
+      println!("{}", line);
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use notify::event::{CreateKind, DataChange};
+
+  /// A fresh directory holding `files`, watched for lines containing "match"
+  fn watched_dir(name: &str, files: &[(&str, &str)]) -> (Tail, Config, PathBuf) {
+    let dir = std::env::temp_dir().join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (file, contents) in files {
+      fs::write(dir.join(file), contents).unwrap();
+    }
+    let config = Config {
+      query: String::from("match"),
+      filepath: dir.display().to_string(),
+      ..Default::default()
+    };
+    let tail = Tail::new(&config).unwrap();
+    (tail, config, dir)
+  }
+
+  fn modified(path: PathBuf) -> Event {
+    Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Any))).add_path(path)
+  }
+
+  #[test]
+  fn truncated_files_are_read_from_the_start() {
+    let (mut tail, config, dir) = watched_dir("greprs_watch_truncate", &[("app.log", "old match\n")]);
+    let log = tail.root.join("app.log");
+    let shown = dir.join("app.log").display().to_string();
+
+    // Lines that were there before watching started aren't printed
+    fs::write(&log, "old match\nnew match\nother\n").unwrap();
+    assert_eq!(vec![format!("{}:new match", shown)], tail.handle(&config, modified(log.clone())));
+
+    // Shorter than before, so it was truncated and everything in it is new
+    fs::write(&log, "match\n").unwrap();
+    assert_eq!(vec![format!("{}:match", shown)], tail.handle(&config, modified(log.clone())));
+
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn rotated_files_start_over() {
+    let (mut tail, config, dir) = watched_dir(
+      "greprs_watch_rotate",
+      &[("app.log", "first match\n"), ("app.log.1", "older match\n")],
+    );
+    let (log, rotated) = (tail.root.join("app.log"), tail.root.join("app.log.1"));
+
+    // app.log is renamed over app.log.1, then a new app.log is started
+    fs::rename(&log, &rotated).unwrap();
+    let rename = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+      .add_path(log.clone())
+      .add_path(rotated.clone());
+    assert!(tail.handle(&config, rename).is_empty());
+
+    fs::write(&log, "second match\n").unwrap();
+    let created = Event::new(EventKind::Create(CreateKind::File)).add_path(log.clone());
+    assert_eq!(
+      vec![format!("{}:second match", dir.join("app.log").display())],
+      tail.handle(&config, created)
+    );
+
+    // The rotated file's old contents aren't printed again when it changes
+    fs::write(&rotated, "first match\nlate match\n").unwrap();
+    assert_eq!(
+      vec![format!("{}:late match", dir.join("app.log.1").display())],
+      tail.handle(&config, modified(rotated))
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
\ No newline at end of file
@@ -0,0 +1,44 @@
+use std::{
+  env,
+  fs,
+  path::{Path, PathBuf},
+  process::{Command, Output},
+};
+
+/// Runs the greprs binary from inside `tests/fixtures`,
+/// so paths in the output are short and the same on every machine
+pub fn greprs(args: &[&str]) -> Output {
+  greprs_in(&fixtures_dir(), args)
+}
+
+pub fn greprs_in(dir: &Path, args: &[&str]) -> Output {
+  Command::new(env!("CARGO_BIN_EXE_greprs"))
+    .args(args)
+    .current_dir(dir)
+    .output()
+    .expect("failed to run greprs")
+}
+
+pub fn fixtures_dir() -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures")
+}
+
+/// Checks `actual` against `tests/golden/<name>.txt`.
+/// Run with `UPDATE_GOLDEN=1 cargo test` to (re)write the golden files
+/// after an intended change, then review the diff before committing.
+pub fn assert_golden(name: &str, actual: &[u8]) {
+  let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("tests")
+    .join("golden")
+    .join(format!("{}.txt", name));
+  let actual = String::from_utf8_lossy(actual);
+
+  if env::var_os("UPDATE_GOLDEN").is_some() {
+    fs::write(&path, actual.as_bytes()).unwrap();
+    return;
+  }
+
+  let expected = fs::read_to_string(&path)
+    .unwrap_or_else(|_| panic!("Missing golden file {}, run with UPDATE_GOLDEN=1", path.display()));
+  assert_eq!(expected, actual, "Output doesn't match {}", path.display());
+}
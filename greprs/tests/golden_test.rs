@@ -0,0 +1,140 @@
+mod common;
+
+use std::{env, fs, path::PathBuf, process};
+use common::{assert_golden, greprs, greprs_in};
+
+/// Runs greprs and checks that it succeeded and printed what the golden file says
+fn check(name: &str, args: &[&str]) {
+  let output = greprs(args);
+  assert!(
+    output.status.success(),
+    "greprs {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr)
+  );
+  assert_golden(name, &output.stdout);
+}
+
+#[test]
+fn literal_single_file() {
+  check("literal_single_file", &["nobody", "tree/poem.txt"]);
+}
+
+#[test]
+fn ignore_case_directory() {
+  // Also checks that .hidden is skipped
+  check("ignore_case_directory", &["-i", "todo", "tree"]);
+}
+
+#[test]
+fn regex_directory() {
+  check("regex_directory", &["-r", "^How [a-z]+", "tree"]);
+}
+
+#[test]
+fn csv_column() {
+  check("csv_column", &["--csv-column", "city", "Paris", "tree/data/people.csv"]);
+}
+
+#[test]
+fn json_path() {
+  check("json_path", &["--json-path", ".user.name", "error", "tree/logs/app.jsonl"]);
+}
+
+#[test]
+fn help_message() {
+  check("help_message", &["--help"]);
+}
+
+#[test]
+fn no_arguments() {
+  let output = greprs(&[]);
+  assert!(!output.status.success());
+  assert_golden("no_arguments", &output.stderr);
+}
+
+#[test]
+fn missing_option_value() {
+  let output = greprs(&["--csv-column"]);
+  assert!(!output.status.success());
+  assert_golden("missing_option_value", &output.stderr);
+}
+
+#[test]
+fn missing_pre_command() {
+  let output = greprs(&["--pre", "greprs-no-such-command", "nobody", "tree/poem.txt"]);
+  // An error, not a panic (which exits with 101)
+  assert_eq!(Some(1), output.status.code());
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert!(stderr.starts_with("Application error: Couldn't read tree/poem.txt: Couldn't run `greprs-no-such-command`"), "{}", stderr);
+}
+
+#[test]
+fn json_without_stats() {
+  let output = greprs(&["--json", "nobody", "tree/poem.txt"]);
+  assert!(!output.status.success());
+  assert_golden("json_without_stats", &output.stderr);
+}
+
+#[test]
+fn stats_stay_out_of_the_matches() {
+  let output = greprs(&["--stats", "--json", "nobody", "tree/poem.txt"]);
+  assert!(output.status.success());
+  assert_golden("literal_single_file", &output.stdout);
+
+  let summary: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+  assert_eq!(1, summary["files_searched"]);
+}
+
+/// An empty `src` directory in a fresh temp dir, for tests that write files
+fn scratch_dir(name: &str) -> PathBuf {
+  let dir = env::temp_dir().join(format!("greprs-{}-{}", name, process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(dir.join("src")).unwrap();
+  dir
+}
+
+#[test]
+fn baseline_inside_the_searched_dir() {
+  let dir = scratch_dir("baseline-inside");
+  fs::write(dir.join("src").join("a.rs"), "// TODO: one\n").unwrap();
+
+  // Saving twice means the baseline exists while the second search runs
+  for _ in 0..2 {
+    assert!(greprs_in(&dir, &["--save-results", "src/todo.json", "TODO", "src"]).status.success());
+  }
+  let output = greprs_in(&dir, &["--compare", "src/todo.json", "TODO", "src"]);
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+  assert_eq!("", String::from_utf8_lossy(&output.stdout));
+
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn compare_with_the_root_typed_differently() {
+  let dir = scratch_dir("root-spelling");
+  fs::write(dir.join("src").join("a.rs"), "// TODO: one\n").unwrap();
+  assert!(greprs_in(&dir, &["--save-results", "todo.json", "TODO", "src"]).status.success());
+
+  let output = greprs_in(&dir, &["--compare", "todo.json", "TODO", "./src"]);
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+  assert_eq!("", String::from_utf8_lossy(&output.stdout));
+
+  fs::write(dir.join("src").join("a.rs"), "// TODO: one\n// TODO: two\n").unwrap();
+  let output = greprs_in(&dir, &["--compare", "todo.json", "TODO", "src/"]);
+  assert!(!output.status.success());
+  assert_eq!("+ a.rs:// TODO: two\n", String::from_utf8_lossy(&output.stdout));
+
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn index_with_escaped_json() {
+  let dir = scratch_dir("index-json");
+  fs::write(dir.join("src").join("app.jsonl"), "{\"msg\": \"caf\\u00e9 open\"}\n").unwrap();
+  assert!(greprs_in(&dir, &["index", "build", "src"]).status.success());
+
+  let output = greprs_in(&dir, &["--index", "--json-path", ".msg", "café", "src"]);
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  assert_eq!("src/app.jsonl:{\"msg\": \"caf\\u00e9 open\"}\n", String::from_utf8_lossy(&output.stdout));
+
+  fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,50 @@
+// Plays the ball following bot against every difficulty of the computer
+// player, as fast as it can. A quick check that the environment is fast
+// enough to train with, and a score to beat. Games are often cut off
+// before anyone wins, so it counts points:
+//
+//     cargo run --release --no-default-features --example benchmark
+
+use std::time::Instant;
+
+use pong::ai::Difficulty;
+use pong::env::{self, Env};
+use pong::settings::Settings;
+
+const GAMES: u32 = 20;
+
+fn main() {
+  for difficulty in [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard] {
+    let mut env = Env::new(Settings::default(), difficulty, 1).expect("classic settings");
+    let start = Instant::now();
+    let mut steps = 0;
+    let (mut scored, mut conceded) = (0, 0);
+
+    for _ in 0..GAMES {
+      let mut observation = env.reset();
+      loop {
+        let (next, reward, done) = env.step(env::follow_ball(&observation));
+        observation = next;
+        steps += 1;
+        if reward > 0.0 {
+          scored += 1;
+        } else if reward < 0.0 {
+          conceded += 1;
+        }
+        if done {
+          break;
+        }
+      }
+    }
+
+    let per_second = steps as f64 / start.elapsed().as_secs_f64();
+    println!(
+      "{}: scored {}, let in {} over {} games, {:.0} steps per second",
+      difficulty.name(),
+      scored,
+      conceded,
+      GAMES,
+      per_second
+    );
+  }
+}
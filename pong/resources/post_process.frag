@@ -0,0 +1,26 @@
+#version 150
+
+// A simple old TV look for the whole screen: the colors split apart
+// a little towards the edges, every other line is a bit darker and
+// the corners fade out.
+
+in vec2 v_uv;
+in vec4 v_color;
+
+uniform sampler2D u_texture;
+
+out vec4 o_color;
+
+void main() {
+  vec2 from_center = v_uv - vec2(0.5);
+  vec2 split = from_center * 0.006;
+
+  float r = texture(u_texture, v_uv + split).r;
+  float g = texture(u_texture, v_uv).g;
+  float b = texture(u_texture, v_uv - split).b;
+
+  float scanline = mod(floor(gl_FragCoord.y), 2.0) == 0.0 ? 1.0 : 0.85;
+  float vignette = 1.0 - dot(from_center, from_center) * 0.8;
+
+  o_color = vec4(vec3(r, g, b) * scanline * vignette, 1.0) * v_color;
+}
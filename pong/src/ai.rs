@@ -0,0 +1,283 @@
+// A computer controlled paddle for single player games.
+//
+// Rather than chasing the ball, it works out where the ball is going to
+// cross its side of the screen (bounces and all) and heads there. To give
+// humans a chance it only looks every so often, can be slower than a
+// human player, doesn't always guess exactly right and every now and
+// then lets a ball go by on purpose.
+
+use crate::rng::Rng;
+use crate::settings::Settings;
+use crate::simulation::{Entity, Simulation, Vec2};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+  Easy,
+  Normal,
+  Hard,
+}
+
+impl Difficulty {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Difficulty::Easy => "Easy",
+      Difficulty::Normal => "Normal",
+      Difficulty::Hard => "Hard",
+    }
+  }
+
+  pub fn next(&self) -> Difficulty {
+    match self {
+      Difficulty::Easy => Difficulty::Normal,
+      Difficulty::Normal => Difficulty::Hard,
+      Difficulty::Hard => Difficulty::Easy,
+    }
+  }
+
+  pub fn previous(&self) -> Difficulty {
+    match self {
+      Difficulty::Easy => Difficulty::Hard,
+      Difficulty::Normal => Difficulty::Easy,
+      Difficulty::Hard => Difficulty::Normal,
+    }
+  }
+
+  /// Seconds between each time the AI looks at the ball
+  fn reaction_delay(&self) -> f32 {
+    match self {
+      Difficulty::Easy => 0.3,
+      Difficulty::Normal => 0.15,
+      Difficulty::Hard => 0.05,
+    }
+  }
+
+  /// How fast the AI can move its paddle, compared to a human
+  fn max_speed(&self) -> f32 {
+    match self {
+      Difficulty::Easy => 0.5,
+      Difficulty::Normal => 0.75,
+      Difficulty::Hard => 1.0,
+    }
+  }
+
+  /// The most (in pixels) the AI's guess of where the ball ends up can be off by.
+  /// It's always less than the paddle can reach, so this alone never loses a point.
+  fn prediction_error(&self) -> f32 {
+    match self {
+      Difficulty::Easy => 60.0,
+      Difficulty::Normal => 25.0,
+      Difficulty::Hard => 5.0,
+    }
+  }
+
+  /// How likely the AI is to misjudge a ball coming its way badly enough to miss it.
+  /// Without this a good enough guess and a fast enough paddle never lose a point.
+  fn miss_chance(&self) -> f32 {
+    match self {
+      Difficulty::Easy => 0.3,
+      Difficulty::Normal => 0.15,
+      Difficulty::Hard => 0.05,
+    }
+  }
+}
+
+pub struct Ai {
+  difficulty: Difficulty,
+  // Which of the simulation's paddles it moves
+  paddle: usize,
+  rng: Rng,
+  // Counts down to the next time the AI looks at the ball
+  reaction_timer: f32,
+  // Where the AI wants the center of its paddle to be, along the way the paddle moves
+  target: f32,
+  // Whether a ball was coming this way the last time the AI looked,
+  // and if it's decided to miss that ball, how far off it's aiming
+  tracking: bool,
+  miss: f32,
+}
+
+impl Ai {
+  pub fn new(difficulty: Difficulty, paddle: usize, rng: Rng) -> Ai {
+    Ai {
+      difficulty,
+      paddle,
+      rng,
+      reaction_timer: 0.0,
+      // Replaced the first time the AI looks at the ball
+      target: 0.0,
+      tracking: false,
+      miss: 0.0,
+    }
+  }
+
+  /// Which of the simulation's paddles this AI moves
+  pub fn paddle(&self) -> usize {
+    self.paddle
+  }
+
+  /// Decides which way to move the paddle this step, the same way
+  /// a keyboard would: -1.0 is up (or left), 1.0 is down (or right)
+  pub fn input(&mut self, sim: &Simulation, dt: f32) -> f32 {
+    let Some(paddle) = sim.paddles.get(self.paddle) else {
+      return 0.0;
+    };
+
+    self.reaction_timer -= dt;
+    if self.reaction_timer <= 0.0 {
+      self.reaction_timer = self.difficulty.reaction_delay();
+      self.target = self.plan(sim, paddle);
+    }
+
+    // Move at most as far as needed to reach the target this step,
+    // so the paddle doesn't shake back and forth around it
+    let distance = self.target - axes(paddle, paddle.center()).y;
+    let max_speed = self.difficulty.max_speed();
+    (distance / (sim.settings.paddle_speed * dt)).clamp(-max_speed, max_speed)
+  }
+
+  fn plan(&mut self, sim: &Simulation, paddle: &Entity) -> f32 {
+    let Some(side) = paddle.player().map(|player| player.side()) else {
+      return 0.0;
+    };
+    // With more than one ball, go for whichever gets here first
+    let time_to_reach = |ball: &&Entity| {
+      let (ball_position, ball_velocity) = (axes(paddle, ball.position), axes(paddle, ball.velocity));
+      ((axes(paddle, paddle.position).x - ball_position.x) / ball_velocity.x).abs()
+    };
+    let first = sim
+      .balls
+      .iter()
+      .filter(|ball| side.facing(ball.velocity))
+      .min_by(|a, b| time_to_reach(a).total_cmp(&time_to_reach(b)));
+    let Some(ball) = first else {
+      self.tracking = false;
+      // Wait in the middle, where it's easiest to get anywhere from
+      return field_length(paddle, &sim.settings) / 2.0;
+    };
+
+    let predicted = predict_ball(ball, paddle, &sim.settings);
+    let error = self.difficulty.prediction_error();
+    // Once per ball heading this way, decide whether this is the one to miss
+    if !self.tracking {
+      self.tracking = true;
+      self.miss = if self.rng.next_f32() < self.difficulty.miss_chance() {
+        // Far enough past the end of the paddle that the usual error can't
+        // bring it back, towards the middle so the wall doesn't stop it
+        let reach = (axes(paddle, paddle.size).y + axes(paddle, ball.size).y) / 2.0;
+        let miss = reach + error + axes(paddle, ball.size).y;
+        if predicted < field_length(paddle, &sim.settings) / 2.0 { miss } else { -miss }
+      } else {
+        0.0
+      };
+    }
+    predicted + self.miss + self.rng.range(-error, error)
+  }
+}
+
+/// Paddles on the top and bottom work just like the ones on the sides,
+/// with x and y swapped. This turns `v` around to match `paddle`, so that
+/// y is always the way the paddle moves and x is towards it.
+fn axes(paddle: &Entity, v: Vec2) -> Vec2 {
+  match paddle.player() {
+    Some(player) if !player.side().is_vertical() => Vec2::new(v.y, v.x),
+    _ => v,
+  }
+}
+
+/// How far a paddle can move
+fn field_length(paddle: &Entity, settings: &Settings) -> f32 {
+  axes(paddle, Vec2::new(settings.window_width, settings.window_height)).y
+}
+
+/// Where the center of the ball will be when it reaches the front of `paddle`,
+/// along the way the paddle moves, taking bounces off the walls into account
+pub fn predict_ball(ball: &Entity, paddle: &Entity, settings: &Settings) -> f32 {
+  let (position, velocity, size) = (axes(paddle, ball.position), axes(paddle, ball.velocity), axes(paddle, ball.size));
+  let (paddle_position, paddle_size) = (axes(paddle, paddle.position), axes(paddle, paddle.size));
+
+  let front = if velocity.x < 0.0 {
+    paddle_position.x + paddle_size.x
+  } else {
+    paddle_position.x - size.x
+  };
+  let time = (front - position.x) / velocity.x;
+  if !time.is_finite() || time < 0.0 {
+    return position.y + size.y / 2.0;
+  }
+
+  // Pretend there are no walls, then fold the result back onto the screen.
+  // Every time the ball would go past a wall it's mirrored back.
+  let range = field_length(paddle, settings) - size.y;
+  let y = (position.y + velocity.y * time).rem_euclid(2.0 * range);
+  let y = if y > range { 2.0 * range - y } else { y };
+
+  y + size.y / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::simulation::{Inputs, TIMESTEP};
+
+  const PADDLE: Vec2 = Vec2::new(24.0, 104.0);
+  const BALL: Vec2 = Vec2::new(22.0, 22.0);
+
+  #[test]
+  fn predicts_straight_line() {
+    let mut sim = Simulation::new(Settings::default(), 1, PADDLE, PADDLE, BALL);
+    sim.balls[0].position.y = 100.0;
+    sim.balls[0].velocity = Vec2::new(300.0, 0.0);
+
+    assert_eq!(100.0 + BALL.y / 2.0, predict_ball(&sim.balls[0], &sim.paddles[1], &sim.settings));
+  }
+
+  #[test]
+  fn predicts_wall_bounce() {
+    let mut sim = Simulation::new(Settings::default(), 1, PADDLE, PADDLE, BALL);
+    // Heading up at 45 degrees, 100 pixels from the top wall,
+    // so it bounces and comes back down the rest of the way
+    sim.balls[0].position = Vec2::new(300.0, 100.0);
+    sim.balls[0].velocity = Vec2::new(300.0, -300.0);
+    let distance = sim.paddles[1].position.x - BALL.x - 300.0;
+
+    let expected = distance - 100.0 + BALL.y / 2.0;
+    assert!((expected - predict_ball(&sim.balls[0], &sim.paddles[1], &sim.settings)).abs() < 0.01);
+  }
+
+  #[test]
+  fn hard_ai_returns_the_ball() {
+    let mut sim = Simulation::new(Settings::default(), 1, PADDLE, PADDLE, BALL);
+    sim.balls[0].velocity = Vec2::new(300.0, 200.0);
+    let mut ai = Ai::new(Difficulty::Hard, 1, Rng::new(1));
+
+    for _ in 0..5 * 60 {
+      let inputs = Inputs::two(0.0, ai.input(&sim, TIMESTEP));
+      sim.step(inputs, TIMESTEP);
+      if sim.balls[0].velocity.x < 0.0 {
+        return;
+      }
+    }
+    panic!("the AI never hit the ball back");
+  }
+
+  /// Points `difficulty` lets in over three minutes, against a hard AI on the other side
+  fn conceded(difficulty: Difficulty) -> i32 {
+    let settings = Settings { winning_score: 1000, ..Settings::default() };
+    let mut sim = Simulation::new(settings, 1, PADDLE, PADDLE, BALL);
+    let mut left = Ai::new(Difficulty::Hard, 0, Rng::new(1));
+    let mut right = Ai::new(difficulty, 1, Rng::new(2));
+
+    for _ in 0..3 * 60 * 60 {
+      let inputs = Inputs::two(left.input(&sim, TIMESTEP), right.input(&sim, TIMESTEP));
+      sim.step(inputs, TIMESTEP);
+    }
+    sim.scores[0]
+  }
+
+  #[test]
+  fn easier_ais_let_in_more_points() {
+    let (easy, normal, hard) = (conceded(Difficulty::Easy), conceded(Difficulty::Normal), conceded(Difficulty::Hard));
+    assert!(easy > normal && normal > hard, "easy {}, normal {}, hard {}", easy, normal, hard);
+    assert!(hard > 0);
+  }
+}
@@ -0,0 +1,95 @@
+// Sound effects and music. Sound is nice to have but the game works
+// without it, so nothing in here ever stops the game: a missing file or
+// a machine without an audio device just means playing in silence.
+
+use tetra::audio::{self, Sound, SoundInstance};
+use tetra::Context;
+
+use crate::resources::Sounds;
+use crate::settings::Settings;
+use crate::simulation::{Event, Simulation};
+
+pub struct Audio {
+  paddle: Option<Sound>,
+  wall: Option<Sound>,
+  score: Option<Sound>,
+  win: Option<Sound>,
+  music: Option<SoundInstance>,
+  // Set the first time a sound can't be played, so the warning
+  // isn't printed for every single bounce
+  failed: bool,
+}
+
+impl Audio {
+  pub fn new(ctx: &mut Context, sounds: &Sounds, settings: &Settings) -> Audio {
+    let mut audio = Audio {
+      paddle: sounds.paddle.clone(),
+      wall: sounds.wall.clone(),
+      score: sounds.score.clone(),
+      win: sounds.win.clone(),
+      music: None,
+      failed: false,
+    };
+
+    // The music just keeps looping in the background
+    if let Some(music) = &sounds.music {
+      match music.repeat_with(ctx, 0.5, 1.0) {
+        Ok(instance) => audio.music = Some(instance),
+        Err(err) => audio.warn(err),
+      }
+    }
+
+    audio.set_volume(ctx, settings);
+    audio
+  }
+
+  /// Call whenever the volume settings change
+  pub fn set_volume(&self, ctx: &mut Context, settings: &Settings) {
+    let volume = if settings.muted { 0.0 } else { settings.volume };
+    audio::set_master_volume(ctx, volume);
+  }
+
+  /// The music stops while the game is paused
+  pub fn pause_music(&self, paused: bool) {
+    if let Some(music) = &self.music {
+      if paused {
+        music.pause();
+      } else {
+        music.play();
+      }
+    }
+  }
+
+  /// Plays the sounds for what happened during the last step
+  pub fn play(&mut self, ctx: &Context, events: &[Event], sim: &Simulation) {
+    for event in events {
+      let (sound, speed) = match event {
+        // The faster the ball is going, the higher the note.
+        // With more than one ball, the fastest one decides.
+        Event::PaddleHit(_) => {
+          let fastest = sim.balls.iter().map(|ball| ball.velocity.length()).fold(0.0, f32::max);
+          let speed = fastest / sim.settings.ball_speed;
+          (&self.paddle, speed.sqrt().clamp(0.8, 2.0))
+        },
+        Event::WallBounce => (&self.wall, 1.0),
+        // Same as a point, but higher
+        Event::PowerUp(..) => (&self.score, 1.5),
+        Event::Scored(_) | Event::LostLife(_) => (&self.score, 1.0),
+        // Same as winning, but lower
+        Event::Eliminated(_) => (&self.win, 0.75),
+        Event::Won(_) => (&self.win, 1.0),
+      };
+
+      if let Some(Err(err)) = sound.as_ref().map(|sound| sound.play_with(ctx, 1.0, speed)) {
+        self.warn(err);
+      }
+    }
+  }
+
+  fn warn(&mut self, err: tetra::TetraError) {
+    if !self.failed {
+      eprintln!("Couldn't play sound, carrying on without it: {}", err);
+      self.failed = true;
+    }
+  }
+}
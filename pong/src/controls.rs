@@ -0,0 +1,362 @@
+// Turns keys and gamepads into what the players want to do, so nothing
+// else in the game has to know which key is which. Every action can be
+// rebound from the controls screen, and the bindings are saved next to the
+// settings file.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tetra::input::{self, GamepadAxis, GamepadButton, Key};
+use tetra::Context;
+
+use crate::settings::Settings;
+use crate::simulation::Player;
+
+/// Something a player can do with a key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+  Player1Up,
+  Player1Down,
+  Player2Up,
+  Player2Down,
+  // Players 3 and 4 guard the top and bottom in the modes with more than two
+  // sides, so for them up and down mean left and right
+  Player3Up,
+  Player3Down,
+  Player4Up,
+  Player4Down,
+  Serve,
+  Pause,
+}
+
+/// Every action, in the order the controls screen lists them
+pub const ACTIONS: [Action; 10] = [
+  Action::Player1Up,
+  Action::Player1Down,
+  Action::Player2Up,
+  Action::Player2Down,
+  Action::Player3Up,
+  Action::Player3Down,
+  Action::Player4Up,
+  Action::Player4Down,
+  Action::Serve,
+  Action::Pause,
+];
+
+impl Action {
+  pub fn name(self) -> &'static str {
+    match self {
+      Action::Player1Up => "Player 1 Up",
+      Action::Player1Down => "Player 1 Down",
+      Action::Player2Up => "Player 2 Up",
+      Action::Player2Down => "Player 2 Down",
+      Action::Player3Up => "Player 3 Up/Left",
+      Action::Player3Down => "Player 3 Down/Right",
+      Action::Player4Up => "Player 4 Up/Left",
+      Action::Player4Down => "Player 4 Down/Right",
+      Action::Serve => "Serve",
+      Action::Pause => "Pause",
+    }
+  }
+}
+
+// Keys that can't be rebound. These work on every screen
+pub const MUTE: Key = Key::M;
+pub const VOLUME_DOWN: Key = Key::Minus;
+pub const VOLUME_UP: Key = Key::Equals;
+pub const FULLSCREEN: Key = Key::F11;
+// and these while paused, where the pause and serve actions are read too
+pub const RESTART: Key = Key::R;
+pub const TO_TITLE: Key = Key::T;
+
+// Binding one of these would make a key do two things at once.
+// Keys only used on screens that don't read the actions (like P on the
+// leaderboard or F while watching a replay) are fine to bind.
+const RESERVED: [Key; 7] = [Key::Escape, MUTE, VOLUME_DOWN, VOLUME_UP, FULLSCREEN, RESTART, TO_TITLE];
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+// Anything missing from the file keeps its default
+#[serde(default)]
+pub struct Controls {
+  // Keys are saved by name, like `player1_up = "W"`
+  #[serde(with = "key_name")]
+  pub player1_up: Key,
+  #[serde(with = "key_name")]
+  pub player1_down: Key,
+  #[serde(with = "key_name")]
+  pub player2_up: Key,
+  #[serde(with = "key_name")]
+  pub player2_down: Key,
+  #[serde(with = "key_name")]
+  pub player3_up: Key,
+  #[serde(with = "key_name")]
+  pub player3_down: Key,
+  #[serde(with = "key_name")]
+  pub player4_up: Key,
+  #[serde(with = "key_name")]
+  pub player4_down: Key,
+  #[serde(with = "key_name")]
+  pub serve: Key,
+  #[serde(with = "key_name")]
+  pub pause: Key,
+
+  // How far a gamepad stick has to be pushed before the paddle moves,
+  // from 0.0 to 1.0. Sticks never sit exactly in the middle.
+  pub deadzone: f32,
+}
+
+impl Default for Controls {
+  fn default() -> Controls {
+    Controls {
+      player1_up: Key::W,
+      player1_down: Key::S,
+      player2_up: Key::Up,
+      player2_down: Key::Down,
+      player3_up: Key::I,
+      player3_down: Key::K,
+      player4_up: Key::NumPad8,
+      player4_down: Key::NumPad5,
+      serve: Key::Space,
+      pause: Key::P,
+      deadzone: 0.2,
+    }
+  }
+}
+
+impl Controls {
+  /// Where the bindings are saved: `controls.toml` next to the settings file
+  pub fn path() -> Option<PathBuf> {
+    Settings::path().map(|path| path.with_file_name("controls.toml"))
+  }
+
+  /// Reads the bindings file. A missing file just means the defaults.
+  pub fn load(path: &Path) -> Result<Controls, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+      Ok(contents) => Controls::from_toml(&contents),
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Controls::default()),
+      Err(err) => Err(err.into()),
+    }
+  }
+
+  pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+      fs::create_dir_all(dir)?;
+    }
+    fs::write(path, self.to_toml()?)?;
+    Ok(())
+  }
+
+  pub fn from_toml(contents: &str) -> Result<Controls, Box<dyn Error>> {
+    let controls: Controls = toml::from_str(contents)?;
+    if !(0.0..0.95).contains(&controls.deadzone) {
+      return Err("the deadzone has to be between 0 and 0.95".into());
+    }
+    Ok(controls)
+  }
+
+  pub fn to_toml(self) -> Result<String, Box<dyn Error>> {
+    Ok(toml::to_string(&self)?)
+  }
+
+  pub fn key(&self, action: Action) -> Key {
+    match action {
+      Action::Player1Up => self.player1_up,
+      Action::Player1Down => self.player1_down,
+      Action::Player2Up => self.player2_up,
+      Action::Player2Down => self.player2_down,
+      Action::Player3Up => self.player3_up,
+      Action::Player3Down => self.player3_down,
+      Action::Player4Up => self.player4_up,
+      Action::Player4Down => self.player4_down,
+      Action::Serve => self.serve,
+      Action::Pause => self.pause,
+    }
+  }
+
+  fn key_mut(&mut self, action: Action) -> &mut Key {
+    match action {
+      Action::Player1Up => &mut self.player1_up,
+      Action::Player1Down => &mut self.player1_down,
+      Action::Player2Up => &mut self.player2_up,
+      Action::Player2Down => &mut self.player2_down,
+      Action::Player3Up => &mut self.player3_up,
+      Action::Player3Down => &mut self.player3_down,
+      Action::Player4Up => &mut self.player4_up,
+      Action::Player4Down => &mut self.player4_down,
+      Action::Serve => &mut self.serve,
+      Action::Pause => &mut self.pause,
+    }
+  }
+
+  /// Binds `key` to `action`. If another action already used that key,
+  /// the two swap so every action still has a key of its own.
+  /// Returns false for keys that can't be bound.
+  pub fn bind(&mut self, action: Action, key: Key) -> bool {
+    if key_name::name(key).is_none() || RESERVED.contains(&key) {
+      return false;
+    }
+    let old = self.key(action);
+    if let Some(&other) = ACTIONS.iter().find(|&&other| self.key(other) == key) {
+      *self.key_mut(other) = old;
+    }
+    *self.key_mut(action) = key;
+    true
+  }
+
+  /// What the controls screen shows for the key bound to `action`
+  pub fn key_label(&self, action: Action) -> &'static str {
+    key_name::name(self.key(action)).unwrap_or("?")
+  }
+
+  pub fn adjust_deadzone(&mut self, steps: i32) {
+    self.deadzone = ((self.deadzone + 0.05 * steps as f32).clamp(0.0, 0.9) * 100.0).round() / 100.0;
+  }
+
+  /// How `player` wants to move their paddle: -1.0 is up (or left), 1.0 is down (or right).
+  /// Keys and the d-pad go full speed, the stick goes as fast as it's pushed.
+  /// Player 1 uses the first gamepad, player 2 the second, and so on.
+  /// `vertical` is whether the paddle moves up and down, rather than sideways.
+  pub fn movement(&self, ctx: &Context, player: Player, vertical: bool) -> f32 {
+    let (up, down) = match player {
+      Player::One => (self.player1_up, self.player1_down),
+      Player::Two => (self.player2_up, self.player2_down),
+      Player::Three => (self.player3_up, self.player3_down),
+      Player::Four => (self.player4_up, self.player4_down),
+    };
+    let gamepad = player.index();
+    // Sideways paddles follow the stick and d-pad sideways
+    let (back, forward, axis) = if vertical {
+      (GamepadButton::Up, GamepadButton::Down, GamepadAxis::LeftStickY)
+    } else {
+      (GamepadButton::Left, GamepadButton::Right, GamepadAxis::LeftStickX)
+    };
+
+    let mut movement = 0.0;
+    if input::is_key_down(ctx, up) || input::is_gamepad_button_down(ctx, gamepad, back) {
+      movement -= 1.0;
+    }
+    if input::is_key_down(ctx, down) || input::is_gamepad_button_down(ctx, gamepad, forward) {
+      movement += 1.0;
+    }
+    if input::is_gamepad_connected(ctx, gamepad) {
+      let stick = input::get_gamepad_axis_position(ctx, gamepad, axis);
+      movement += apply_deadzone(stick, self.deadzone);
+    }
+    movement.clamp(-1.0, 1.0)
+  }
+
+  /// Whether `action` was just pressed. On a gamepad A serves and Start pauses.
+  pub fn pressed(&self, ctx: &Context, action: Action) -> bool {
+    let button = match action {
+      Action::Serve => Some(GamepadButton::A),
+      Action::Pause => Some(GamepadButton::Start),
+      _ => None,
+    };
+    let on_gamepad = |button| (0..4).any(|gamepad| input::is_gamepad_button_pressed(ctx, gamepad, button));
+    input::is_key_pressed(ctx, self.key(action)) || button.is_some_and(on_gamepad)
+  }
+}
+
+/// Ignores a stick position inside the deadzone, and stretches the rest so
+/// pushing the stick just past the deadzone starts from 0.0 instead of jumping
+pub fn apply_deadzone(position: f32, deadzone: f32) -> f32 {
+  if position.abs() <= deadzone {
+    return 0.0;
+  }
+  position.signum() * ((position.abs() - deadzone) / (1.0 - deadzone)).min(1.0)
+}
+
+/// The names keys are saved as. Tetra's keys can't be saved on their own,
+/// and only the keys in here can be bound.
+pub mod key_name {
+  use serde::{de, Deserialize, Deserializer, Serializer};
+  use tetra::input::Key;
+
+  const KEYS: [(Key, &str); 70] = [
+    (Key::A, "A"), (Key::B, "B"), (Key::C, "C"), (Key::D, "D"), (Key::E, "E"),
+    (Key::F, "F"), (Key::G, "G"), (Key::H, "H"), (Key::I, "I"), (Key::J, "J"),
+    (Key::K, "K"), (Key::L, "L"), (Key::M, "M"), (Key::N, "N"), (Key::O, "O"),
+    (Key::P, "P"), (Key::Q, "Q"), (Key::R, "R"), (Key::S, "S"), (Key::T, "T"),
+    (Key::U, "U"), (Key::V, "V"), (Key::W, "W"), (Key::X, "X"), (Key::Y, "Y"),
+    (Key::Z, "Z"),
+    (Key::Num0, "0"), (Key::Num1, "1"), (Key::Num2, "2"), (Key::Num3, "3"), (Key::Num4, "4"),
+    (Key::Num5, "5"), (Key::Num6, "6"), (Key::Num7, "7"), (Key::Num8, "8"), (Key::Num9, "9"),
+    (Key::NumPad0, "NumPad0"), (Key::NumPad1, "NumPad1"), (Key::NumPad2, "NumPad2"),
+    (Key::NumPad3, "NumPad3"), (Key::NumPad4, "NumPad4"), (Key::NumPad5, "NumPad5"),
+    (Key::NumPad6, "NumPad6"), (Key::NumPad7, "NumPad7"), (Key::NumPad8, "NumPad8"),
+    (Key::NumPad9, "NumPad9"),
+    (Key::Up, "Up"), (Key::Down, "Down"), (Key::Left, "Left"), (Key::Right, "Right"),
+    (Key::Space, "Space"), (Key::Enter, "Enter"), (Key::Tab, "Tab"), (Key::Backspace, "Backspace"),
+    (Key::LeftShift, "LeftShift"), (Key::RightShift, "RightShift"),
+    (Key::LeftCtrl, "LeftCtrl"), (Key::RightCtrl, "RightCtrl"),
+    (Key::LeftAlt, "LeftAlt"), (Key::RightAlt, "RightAlt"),
+    (Key::Comma, "Comma"), (Key::Period, "Period"), (Key::Slash, "Slash"),
+    (Key::Minus, "Minus"), (Key::Equals, "Equals"), (Key::Escape, "Escape"),
+    (Key::F1, "F1"), (Key::F2, "F2"), (Key::F3, "F3"), (Key::F4, "F4"),
+  ];
+
+  pub fn name(key: Key) -> Option<&'static str> {
+    KEYS.iter().find(|(k, _)| *k == key).map(|(_, name)| *name)
+  }
+
+  pub fn key(name: &str) -> Option<Key> {
+    KEYS.iter().find(|(_, n)| n.eq_ignore_ascii_case(name)).map(|(key, _)| *key)
+  }
+
+  pub fn serialize<S: Serializer>(key: &Key, serializer: S) -> Result<S::Ok, S::Error> {
+    let name = name(*key).ok_or_else(|| serde::ser::Error::custom(format!("can't save key {:?}", key)))?;
+    serializer.serialize_str(name)
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Key, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    key(&name).ok_or_else(|| de::Error::custom(format!("unknown key: {}", name)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn survives_saving_and_loading() {
+    let controls = Controls { player1_up: Key::NumPad8, serve: Key::Enter, deadzone: 0.3, ..Controls::default() };
+    let contents = controls.to_toml().unwrap();
+
+    assert!(contents.contains("player1_up = \"NumPad8\""), "{}", contents);
+    assert_eq!(controls, Controls::from_toml(&contents).unwrap());
+    assert_eq!(Key::Z, Controls::from_toml("pause = \"z\"").unwrap().pause);
+    assert!(Controls::from_toml("pause = \"Jetpack\"").is_err());
+  }
+
+  #[test]
+  fn rebinding_a_used_key_swaps() {
+    let mut controls = Controls::default();
+
+    assert!(controls.bind(Action::Player1Up, Key::Down));
+    assert_eq!(Key::Down, controls.player1_up);
+    assert_eq!(Key::W, controls.player2_down);
+    assert!(!controls.bind(Action::Serve, Key::M));
+    assert_eq!(Key::Space, controls.serve);
+  }
+
+  #[test]
+  fn keys_used_while_paused_cant_be_bound() {
+    let mut controls = Controls::default();
+
+    assert!(!controls.bind(Action::Serve, RESTART));
+    assert!(!controls.bind(Action::Pause, TO_TITLE));
+    assert_eq!((Key::Space, Key::P), (controls.serve, controls.pause));
+    assert!(controls.bind(Action::Serve, Key::F));
+  }
+
+  #[test]
+  fn deadzone() {
+    assert_eq!(0.0, apply_deadzone(0.15, 0.2));
+    assert_eq!(0.0, apply_deadzone(-0.2, 0.2));
+    assert!((apply_deadzone(0.6, 0.2) - 0.5).abs() < 1e-6);
+    assert_eq!(-1.0, apply_deadzone(-1.0, 0.2));
+  }
+}
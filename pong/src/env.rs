@@ -0,0 +1,220 @@
+// Pong as an environment for training bots, with reinforcement learning or
+// anything else. It's the same simulation the game runs, just without a
+// window, sound or tetra, so it goes as fast as the CPU can step it.
+//
+// The bot plays player 1 on the left, against the same computer player as
+// the one player game on the right. Every step is one tick of the game:
+//
+//     let mut env = Env::new(Settings::default(), Difficulty::Normal, 1)?;
+//     let mut observation = env.reset();
+//     loop {
+//       let (next, reward, done) = env.step(my_bot(&observation));
+//       ...
+//     }
+
+use crate::ai::{Ai, Difficulty};
+use crate::rng::Rng;
+use crate::settings::{Mode, Settings};
+use crate::simulation::{Event, Inputs, Player, Simulation, Vec2, TICKS_PER_SECOND, TIMESTEP};
+
+// Same sizes as the textures the game uses
+pub const PADDLE_SIZE: Vec2 = Vec2::new(24.0, 104.0);
+pub const BALL_SIZE: Vec2 = Vec2::new(22.0, 22.0);
+// Games that go on for longer than 5 minutes are cut off
+pub const MAX_STEPS: u32 = 5 * 60 * TICKS_PER_SECOND as u32;
+
+/// For bots that pick from a few moves instead of giving a number
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+  Up,
+  Stay,
+  Down,
+}
+
+pub const ACTIONS: [Action; 3] = [Action::Up, Action::Stay, Action::Down];
+
+impl From<Action> for f32 {
+  fn from(action: Action) -> f32 {
+    match action {
+      Action::Up => -1.0,
+      Action::Stay => 0.0,
+      Action::Down => 1.0,
+    }
+  }
+}
+
+/// What the bot gets to see. Positions are fractions of the field (0.0 to 1.0)
+/// and velocities are in fields per second, so none of it depends on how big
+/// the field is.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Observation {
+  // The middle of the ball. With multi-ball on, only the first one.
+  pub ball: [f32; 2],
+  pub ball_velocity: [f32; 2],
+  // How far down the field the middle of each paddle is
+  pub paddle: f32,
+  pub opponent: f32,
+}
+
+impl Observation {
+  /// All of it in one array, for handing to a neural network
+  pub fn to_array(self) -> [f32; 6] {
+    let [x, y] = self.ball;
+    let [vx, vy] = self.ball_velocity;
+    [x, y, vx, vy, self.paddle, self.opponent]
+  }
+}
+
+pub struct Env {
+  sim: Simulation,
+  opponent: Ai,
+  difficulty: Difficulty,
+  // Seeds every game, so the same seed always gives the same run of games
+  rng: Rng,
+  // Steps so far in this game
+  steps: u32,
+  // After this many steps a game is over even if nobody has won
+  pub max_steps: u32,
+}
+
+impl Env {
+  /// Sets up the first game, `reset` isn't needed before the first `step`.
+  /// Only classic games work, there's one paddle for the bot and one for the opponent.
+  pub fn new(settings: Settings, difficulty: Difficulty, seed: u64) -> Result<Env, String> {
+    if settings.mode != Mode::Classic {
+      return Err(format!("bots can only play classic games, not {}", settings.mode.name()));
+    }
+
+    let mut rng = Rng::new(seed);
+    let sim = Simulation::new(settings, rng.next_u64(), PADDLE_SIZE, PADDLE_SIZE, BALL_SIZE);
+    let opponent = Ai::new(difficulty, 1, Rng::new(rng.next_u64()));
+    Ok(Env { sim, opponent, difficulty, rng, steps: 0, max_steps: MAX_STEPS })
+  }
+
+  /// Starts a new game
+  pub fn reset(&mut self) -> Observation {
+    self.sim.seed = self.rng.next_u64();
+    self.sim.restart();
+    self.opponent = Ai::new(self.difficulty, 1, Rng::new(self.rng.next_u64()));
+    self.steps = 0;
+    self.observe()
+  }
+
+  /// Moves the bot's paddle for one tick, anywhere from -1.0 (full speed up) to
+  /// 1.0 (full speed down), or with an `Action`. The reward is 1.0 when the bot
+  /// scores and -1.0 when the opponent does. Once `done` the game is over
+  /// and the next one starts with `reset`.
+  pub fn step(&mut self, action: impl Into<f32>) -> (Observation, f32, bool) {
+    let opponent = self.opponent.input(&self.sim, TIMESTEP);
+    let events = self.sim.step(Inputs::two(action.into(), opponent), TIMESTEP);
+    self.steps += 1;
+
+    let reward = events
+      .iter()
+      .map(|event| match event {
+        Event::Scored(Player::One) => 1.0,
+        Event::Scored(_) => -1.0,
+        _ => 0.0,
+      })
+      .sum();
+    let done = self.sim.winner.is_some() || self.steps >= self.max_steps;
+    (self.observe(), reward, done)
+  }
+
+  /// Everything about the game, for bots that want to see more than the
+  /// observation (like the power-ups) and for checking the results
+  pub fn simulation(&self) -> &Simulation {
+    &self.sim
+  }
+
+  fn observe(&self) -> Observation {
+    let (width, height) = (self.sim.settings.window_width, self.sim.settings.window_height);
+    let ball = &self.sim.balls[0];
+    let center = ball.center();
+    Observation {
+      ball: [center.x / width, center.y / height],
+      ball_velocity: [ball.velocity.x / width, ball.velocity.y / height],
+      paddle: self.sim.paddles[0].center().y / height,
+      opponent: self.sim.paddles[1].center().y / height,
+    }
+  }
+}
+
+/// A simple bot to measure others against: it just follows the ball
+pub fn follow_ball(observation: &Observation) -> Action {
+  // Close enough is good enough, otherwise it shakes up and down
+  let offset = observation.ball[1] - observation.paddle;
+  if offset < -0.02 {
+    Action::Up
+  } else if offset > 0.02 {
+    Action::Down
+  } else {
+    Action::Stay
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Plays a whole game with `bot`, returning every reward it got
+  fn play(env: &mut Env, bot: impl Fn(&Observation) -> Action) -> Vec<f32> {
+    let mut observation = env.reset();
+    let mut rewards = Vec::new();
+    loop {
+      let (next, reward, done) = env.step(bot(&observation));
+      rewards.push(reward);
+      observation = next;
+      if done {
+        return rewards;
+      }
+    }
+  }
+
+  #[test]
+  fn rewards_match_the_score() {
+    let mut env = Env::new(Settings::default(), Difficulty::Easy, 1).unwrap();
+
+    let rewards = play(&mut env, follow_ball);
+
+    let sim = env.simulation();
+    assert!(sim.winner.is_some());
+    assert_eq!((sim.scores[0] - sim.scores[1]) as f32, rewards.iter().sum::<f32>());
+    assert!(rewards.len() < MAX_STEPS as usize);
+  }
+
+  #[test]
+  fn points_come_early_at_every_difficulty() {
+    // Without any reward there's nothing to learn from, so even the hard
+    // computer player has to let something in well before the cut off
+    for difficulty in [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard] {
+      let mut env = Env::new(Settings::default(), difficulty, 1).unwrap();
+      let rewards = play(&mut env, follow_ball);
+
+      let first = rewards.iter().position(|&reward| reward != 0.0);
+      assert!(first.is_some_and(|step| step < MAX_STEPS as usize / 4), "{:?}: {:?}", difficulty, first);
+    }
+  }
+
+  #[test]
+  fn same_seed_same_games() {
+    let mut first = Env::new(Settings::default(), Difficulty::Normal, 42).unwrap();
+    let mut second = Env::new(Settings::default(), Difficulty::Normal, 42).unwrap();
+
+    for _ in 0..2 {
+      assert_eq!(play(&mut first, follow_ball), play(&mut second, follow_ball));
+      assert_eq!(first.simulation(), second.simulation());
+    }
+  }
+
+  #[test]
+  fn games_are_cut_off() {
+    let mut env = Env::new(Settings::default(), Difficulty::Hard, 1).unwrap();
+    env.max_steps = 10;
+
+    let rewards = play(&mut env, |_| Action::Stay);
+
+    assert_eq!(10, rewards.len());
+    assert!(Env::new(Settings { mode: Mode::FourWay, ..Settings::default() }, Difficulty::Hard, 1).is_err());
+  }
+}
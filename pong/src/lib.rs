@@ -0,0 +1,15 @@
+// Everything about pong that doesn't need a window: the simulation itself,
+// the computer player, replays, network play and stats. The game in main.rs
+// is built on top of this, and so is `env`, which lets bots play without
+// tetra at all (`--no-default-features` leaves tetra out completely).
+
+pub mod ai;
+pub mod env;
+pub mod math;
+pub mod net;
+pub mod power_up;
+pub mod replay;
+pub mod rng;
+pub mod settings;
+pub mod simulation;
+pub mod stats;
@@ -1,275 +1,1130 @@
-use tetra::graphics::{self, Color, Rectangle, Texture, text::{Font, Text}};
+use std::path::PathBuf;
+use std::{env, process};
+
+use tetra::graphics::scaling::{ScalingMode, ScreenScaler};
+use tetra::graphics::{self, Canvas, Color, DrawParams, Texture, text::Text};
 use tetra::input::{self, Key}; // Key Press
 use tetra::math::Vec2;
-use tetra::{Context, ContextBuilder, State};
+use tetra::time::{self, Timestep};
+use tetra::{window, Context, ContextBuilder, State};
 
-// It will be easier to use these values as floats and cast to i32 when
-// we need to.
-const WINDOW_HEIGHT: f32 = 480.0;
-const WINDOW_WIDTH: f32 = 640.0;
+// Everything that needs tetra lives in the game, the rest is in the library
+mod audio;
+mod controls;
+mod resources;
+mod visuals;
 
-// Ball and Paddle Constraints
-const PADDLE_SPEED: f32 = 16.0;
-const PADDLE_SPIN: f32 = 4.0;
-const BALL_SPEED: f32 = 5.0;
-const BALL_ACC: f32 = 0.05;
+use pong::{ai, net, power_up, replay, rng, settings, simulation, stats};
 
-// Game Paramters
-const WINNING_SCORE: i32 = 5;
+use ai::{Ai, Difficulty};
+use audio::Audio;
+use controls::{Action, Controls, ACTIONS};
+use net::{Session, Status};
+use power_up::PowerUp;
+use replay::Replay;
+use resources::{draw_centered, Resources};
+use rng::Rng;
+use settings::{MenuOption, Mode, Settings};
+use simulation::{Entity, EntityType, Event, Inputs, Player, Simulation, MAX_PADDLES, TICKS_PER_SECOND, TIMESTEP};
+use stats::{Stats, Tally};
+use visuals::Visuals;
 
-enum EntityType {
-  Player,
-  Ball,
+/// Where to draw something that moved from `previous` to `current` during the last step.
+/// `blend` is how far we are between that step and the next one (0.0 to 1.0).
+fn interpolate(previous: simulation::Vec2, current: simulation::Vec2, blend: f32) -> Vec2<f32> {
+  Vec2::from(previous + (current - previous) * blend)
 }
 
-struct Entity {
-  texture: Texture,
-  position: Vec2<f32>,
-  velocity: Vec2<f32>,
-  score: i32,
-  entity_type: EntityType,
+fn texture_size(texture: &Texture) -> simulation::Vec2 {
+  simulation::Vec2::new(texture.width() as f32, texture.height() as f32)
 }
 
-impl Entity {
-  fn new(texture: Texture, position: Vec2<f32>) -> Entity {
-    Entity::with_velocity(texture, position, Vec2::zero(), EntityType::Player)
-  }
+/// Where to draw a paddle, stretched to however long power-ups have made it.
+/// Paddles on the top and bottom are the same textures turned on their side.
+fn paddle_params(texture: &Texture, previous: &Entity, current: &Entity, blend: f32) -> DrawParams {
+  let (length, rotation) = if current.is_vertical() {
+    (current.height(), 0.0)
+  } else {
+    (current.width(), std::f32::consts::FRAC_PI_2)
+  };
+  let (width, height) = (texture.width() as f32, texture.height() as f32);
 
-  fn with_velocity(texture: Texture, position: Vec2<f32>, velocity: Vec2<f32>, entity_type: EntityType) -> Entity {
-    Entity {
-      texture,
-      position,
-      velocity,
-      entity_type,
-      score: 0,
-    }
-  }
-
-  // To make the ball collide with the paddles and the walls, we will
-  // implement *axis-aligned bounding boxes* (AABBs). This technique
-  // takes a rectange and does some math to determine if that rectangle
-  // intersects with another rectangle.
+  // Drawn around its center, so turning it doesn't move it
+  DrawParams::new()
+    .position(interpolate(previous.center(), current.center(), blend))
+    .origin(Vec2::new(width / 2.0, height / 2.0))
+    .rotation(rotation)
+    .scale(Vec2::new(1.0, length / height))
+}
 
-  fn width(&self) -> f32 {
-    self.texture.width() as f32
+/// Players 1 and 2 have their own textures, players 3 and 4 borrow them with a different color
+fn player_color(player: Option<Player>) -> Color {
+  match player {
+    Some(Player::Three) => Color::rgb(1.0, 0.85, 0.5),
+    Some(Player::Four) => Color::rgb(0.6, 1.0, 0.6),
+    _ => Color::WHITE,
   }
+}
 
-  fn height(&self) -> f32 {
-    self.texture.height() as f32
+fn power_up_color(power_up: PowerUp) -> Color {
+  match power_up {
+    PowerUp::BigPaddle => Color::rgb(0.2, 0.8, 0.2),
+    PowerUp::SmallPaddle => Color::rgb(0.9, 0.2, 0.2),
+    PowerUp::MultiBall => Color::rgb(1.0, 0.85, 0.1),
+    PowerUp::SpeedUp => Color::rgb(1.0, 0.5, 0.0),
+    PowerUp::Curve => Color::rgb(0.6, 0.3, 0.9),
   }
+}
 
-  fn bounds(&self) -> Rectangle {
-    Rectangle::new(
-      self.position.x,
-      self.position.y,
-      self.width(),
-      self.height(),
-    )
-  }
+/// A fresh seed for a new game
+fn random_seed() -> u64 {
+  Rng::from_time().next_u64()
+}
 
-  fn center(&self) -> Vec2<f32> {
-    Vec2::new(
-      self.position.x + self.width() / 2.0,
-      self.position.y + self.height() / 2.0,
-    )
-  }
+/// Who's controlling the other paddles
+enum Opponent {
+  // Everyone is at the same keyboard
+  Human,
+  // One person against the computer, which plays every other paddle
+  Cpu(Vec<Ai>),
+  // Someone on another machine, we could be either side
+  Remote(Session),
+}
 
-  fn reset(&mut self) -> () {
-    match self.entity_type {
-      EntityType::Player => {
-        self.position.y = (WINDOW_HEIGHT - self.texture.height() as f32) / 2.0
-      },
-      EntityType::Ball => {
-        // used to reset the ball to the center of the screen
-        self.position.x = WINDOW_WIDTH / 2.0 - self.width() / 2.0;
-        self.position.y = WINDOW_HEIGHT / 2.0 - self.height() / 2.0;
-        self.velocity.x = -(self.velocity.x.signum()) * BALL_SPEED;
-        self.velocity.y = 0.0;
-      }
+/// How the game was started from the command line
+enum Start {
+  Local,
+  Network(Session),
+  Replay(Replay),
+}
+
+impl Start {
+  /// What the field is set up with. A replay brings its own settings, which are
+  /// only played back, everything else (like the volume) is still the player's.
+  fn field_settings(&self, settings: Settings) -> Settings {
+    match self {
+      Start::Replay(replay) => replay.settings,
+      _ => settings,
     }
   }
 }
 
+/// Everything main reads from the config directory, and where each one is saved back to
+struct Saved {
+  // Without the command line overrides
+  settings: Settings,
+  settings_path: Option<PathBuf>,
+  controls: Controls,
+  controls_path: Option<PathBuf>,
+  stats: Stats,
+  stats_path: Option<PathBuf>,
+}
+
+/// Watching a replay instead of playing
+struct Playback {
+  replay: Replay,
+  // The next tick to play
+  tick: usize,
+  paused: bool,
+}
+
+/// Which screen the game is on. Each one decides what the keys do,
+/// and only `Playing` moves the simulation forward.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Scene {
+  // Picking the number of players
+  Title,
+  // Every profile's stats, reached from the title
+  Leaderboard,
+  Options,
+  // Rebinding keys, reached from the options
+  Controls,
+  // Waiting for the other player of a network game
+  Connecting,
+  // Waiting for the ball to be served, at the start and after every point
+  Serve,
+  Playing,
+  Paused,
+  GameOver(Player),
+  // The other player of a network game left or stopped answering
+  Disconnected,
+}
+
 struct GameState {
-  player1: Entity,
-  player2: Entity,
-  ball: Entity,
+  scene: Scene,
+  simulation: Simulation,
+  // The simulation as it was before the last step, used to smooth out
+  // movement when the screen refreshes faster than the simulation ticks
+  previous: Simulation,
+  resources: Resources,
   score: Text,
-  winner: String,
+  // What `score` and `clock` say, so they're only changed when they need to be
+  shown_score: Vec<i32>,
+  shown_time: Option<u32>,
+  // Which power-ups are working on who, along the bottom of the screen
+  effects_text: Text,
+  shown_effects: String,
+  opponent: Opponent,
+  difficulty: Difficulty,
+  menu_text: Text,
+  // Our own settings. Network games and replays are played with someone
+  // else's, those only ever go into the simulation and never get saved.
+  settings: Settings,
+  // What's in the settings file, without the command line overrides.
+  // Changes made in the game go into both, and only this one gets saved.
+  file_settings: Settings,
+  // Where the options menu saves to, if there's anywhere to save
+  settings_path: Option<PathBuf>,
+  selected_option: usize,
+  options_text: Text,
+  controls: Controls,
+  controls_path: Option<PathBuf>,
+  selected_control: usize,
+  // Waiting for a key to bind to the selected action
+  rebinding: bool,
+  controls_text: Text,
+  // Whose stats the games count towards, and the game being played's so far
+  stats: Stats,
+  stats_path: Option<PathBuf>,
+  tally: Tally,
+  leaderboard_text: Text,
+  clock: Text,
+  // Shown in the middle of the screen, what it says depends on the scene
+  message: Text,
+  audio: Audio,
+  visuals: Visuals,
+  // Everything is drawn here first, so the whole screen can be shaken
+  // and run through the post-processing shader in one go
+  canvas: Canvas,
+  // Then that's drawn onto the scaler's canvas, which is stretched to fit
+  // the window, with black bars if it's a different shape than the field
+  scaler: ScreenScaler,
+  // Everything that happens in the current game, saved when it's over
+  recording: Option<Replay>,
+  playback: Option<Playback>,
 }
 
 impl GameState {
-  fn new(ctx: &mut Context) -> tetra::Result<GameState> {
-    // Texture is a type that represents image data that has been loaded
-    // into graphics memory.
-
-    /* Player1 Set Up */
-    let player1_texture = Texture::new(ctx, "./resources/player1.png")?;
-    let player1_position = Vec2::new(
-      16.0,
-      // Offset so that the paddle is vertically centered on start up.
-      (WINDOW_HEIGHT - player1_texture.height() as f32) / 2.0,
-    );
+  fn new(ctx: &mut Context, settings: Settings, saved: Saved, start: Start) -> tetra::Result<GameState> {
+    let Saved { settings: file_settings, settings_path, controls, controls_path, stats, stats_path } = saved;
+    let resources = Resources::load(ctx)?;
+    let field = start.field_settings(settings);
 
-    /* Player2 Set Up */
-    let player2_texture = Texture::new(ctx, "./resources/player2.png")?;
-    let player2_position = Vec2::new(
-      WINDOW_WIDTH - player2_texture.width() as f32 - 16.0,
-      // Offset so that the paddle is vertically centered on start up.
-      (WINDOW_HEIGHT - player2_texture.height() as f32) / 2.0,
+    // The simulation only needs to know how big everything is
+    let simulation = Simulation::new(
+      field,
+      random_seed(),
+      texture_size(&resources.player1),
+      texture_size(&resources.player2),
+      texture_size(&resources.ball),
     );
 
-    /* Ball Set Up */
-    let ball_texture = Texture::new(ctx, "./resources/ball.png")?;
-    let ball_position = Vec2::new(
-      WINDOW_WIDTH / 2.0 - ball_texture.width() as f32 / 2.0,
-      WINDOW_HEIGHT / 2.0 - ball_texture.height() as f32 / 2.0,
-    );
-    let ball_veloctiy = Vec2::new(-BALL_SPEED, 0.0);
-
     /* Score Set Up */
-    let score_text = format!("{} - {}", 0, 0);
-    let score_text = Text::new(score_text, Font::vector(ctx, "./resources/Arial.ttf", 32.0)?);
+    let score_text = Text::new("", resources.large_font.clone());
+
+    /* Menu Set Up */
+    let difficulty = Difficulty::Normal;
+    let menu_text = Text::new(menu_content(difficulty, settings.mode, &stats.profile), resources.small_font.clone());
+    let message = Text::new("", resources.large_font.clone());
+    let options_text = Text::new(options_content(&settings, 0), resources.small_font.clone());
+    let controls_text = Text::new(controls_content(&controls, 0, false), resources.small_font.clone());
+    let leaderboard_text = Text::new(leaderboard_content(&stats), resources.small_font.clone());
+    let clock = Text::new("", resources.small_font.clone());
+    let effects_text = Text::new("", resources.small_font.clone());
 
-    Ok(GameState {
-      player1: Entity::new(player1_texture, player1_position),
-      player2: Entity::new(player2_texture, player2_position),
-      ball: Entity::with_velocity(ball_texture, ball_position, ball_veloctiy, EntityType::Ball),
+    /* Sound Set Up */
+    let audio = Audio::new(ctx, &resources.sounds, &settings);
+
+    /* Effects Set Up */
+    let canvas = Canvas::new(ctx, field.window_width as i32, field.window_height as i32)?;
+    let scaler = field_scaler(ctx, &field)?;
+
+    let mut state = GameState {
+      scene: Scene::Title,
+      previous: simulation.clone(),
+      simulation,
+      resources,
       score: score_text,
-      winner: String::from(""),
-    })
+      shown_score: Vec::new(),
+      shown_time: None,
+      effects_text,
+      shown_effects: String::new(),
+      opponent: Opponent::Human,
+      difficulty,
+      menu_text,
+      settings,
+      file_settings,
+      settings_path,
+      selected_option: 0,
+      options_text,
+      controls,
+      controls_path,
+      selected_control: 0,
+      rebinding: false,
+      controls_text,
+      stats,
+      stats_path,
+      tally: Tally::default(),
+      leaderboard_text,
+      clock,
+      message,
+      audio,
+      visuals: Visuals::new(),
+      canvas,
+      scaler,
+      recording: None,
+      playback: None,
+    };
+    match start {
+      Start::Local => {},
+      Start::Network(session) => {
+        state.opponent = Opponent::Remote(session);
+        state.set_scene(Scene::Connecting);
+      },
+      Start::Replay(replay) => {
+        state.playback = Some(Playback { replay, tick: 0, paused: false });
+        state.rewind();
+      },
+    }
+    Ok(state)
   }
-}
 
-impl State for GameState {
-  fn draw(&mut self, ctx: &mut Context) -> tetra::Result {
-    graphics::clear(ctx, Color::rgb(0.392, 0.584, 0.929));
+  fn networked(&self) -> bool {
+    matches!(self.opponent, Opponent::Remote(_))
+  }
 
-    // Draws the paddle to the screen at position (16, 16).
-    // According to the docs, the second parameter is of the type
-    // Into<DrawParams>, but Vec2 is automatically converted to this type.
-    self.player1.texture.draw(ctx, self.player1.position);
-    self.player2.texture.draw(ctx, self.player2.position);
-    if self.winner == "" {
-      self.ball.texture.draw(ctx, self.ball.position);
+  fn set_scene(&mut self, scene: Scene) {
+    self.scene = scene;
+    self.audio.pause_music(scene == Scene::Paused);
+    let message = match scene {
+      Scene::Title | Scene::Leaderboard | Scene::Options | Scene::Controls | Scene::Playing => String::new(),
+      Scene::Connecting => match &self.opponent {
+        Opponent::Remote(session) if session.local_player() == Player::One => {
+          let port = session.local_addr().map(|address| address.port()).unwrap_or_default();
+          format!("Waiting for an opponent on port {}...\n\nT - Title", port)
+        },
+        _ => String::from("Connecting...\n\nT - Title"),
+      },
+      Scene::Serve => format!("Press {} to serve", self.controls.key_label(Action::Serve)),
+      Scene::Paused => format!("Paused\n\n{} - Resume\nR - Restart\nT - Title", self.controls.key_label(Action::Pause)),
+      // Both players would have to agree to play again, so network games just end
+      Scene::GameOver(winner) if self.networked() => format!("{} Wins!\n\nT - Title", winner.name()),
+      Scene::GameOver(winner) => format!("{} Wins!\n\nR - Play Again\nT - Title", winner.name()),
+      Scene::Disconnected => String::from("Opponent disconnected\n\nT - Title"),
+    };
+    self.message.set_content(message);
+  }
+
+  /// Starts a new game at 0 - 0. If the playing field is a different size
+  /// the window stays as it is, the new field is just scaled to fit it.
+  fn start_game(&mut self, ctx: &mut Context, settings: Settings, seed: u64) -> tetra::Result {
+    let current = self.simulation.settings;
+    if (settings.window_width, settings.window_height) != (current.window_width, current.window_height) {
+      self.canvas = Canvas::new(ctx, settings.window_width as i32, settings.window_height as i32)?;
+      self.scaler = field_scaler(ctx, &settings)?;
     }
 
-    // Draw the score
-    let score_text = format!("{} - {}", self.player1.score, self.player2.score);
-    self.score = Text::new(score_text, Font::vector(ctx, "./resources/Arial.ttf", 32.0)?);
-    self.score.draw(ctx, Vec2::new(WINDOW_WIDTH / 2.2, 16.0)); // Doesn't exactly center the text
-    // need to get the bounds of the text and use that to calculate the center
+    self.save_recording();
+    self.simulation.settings = settings;
+    self.simulation.seed = seed;
+    self.simulation.restart();
+    self.previous = self.simulation.clone();
+    self.visuals.clear();
+    self.tally = Tally::default();
+    self.recording = Some(Replay::record(&self.simulation));
+    self.set_scene(Scene::Serve);
+    Ok(())
+  }
 
-    if self.winner != "" {
-      let win_text = format!("{} Wins!", self.winner);
-      let mut winner_text = Text::new(win_text, Font::vector(ctx, "./resources/Arial.ttf", 48.0)?);
-      winner_text.draw(ctx, Vec2::new(WINDOW_WIDTH / 2.0, WINDOW_HEIGHT / 2.0));
-      // Add play again button that resets everything
+  /// Saves the current game's replay, if anything happened in it
+  fn save_recording(&mut self) {
+    let Some(recording) = self.recording.take() else {
+      return;
+    };
+    let Some(dir) = replay::replay_dir() else {
+      return;
+    };
+    if recording.is_empty() {
+      return;
+    }
+
+    let millis = std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .map(|duration| duration.as_millis())
+      .unwrap_or_default();
+    let path = dir.join(format!("{}.pongreplay", millis));
+    match recording.save(&path) {
+      Ok(()) => eprintln!("Saved replay to {}", path.display()),
+      Err(err) => eprintln!("Couldn't save replay to {}: {}", path.display(), err),
     }
+  }
 
+  fn update_title(&mut self, ctx: &mut Context) -> tetra::Result {
+    if input::is_key_pressed(ctx, Key::Num1) {
+      let ais = (1..self.settings.mode.paddles()).map(|paddle| Ai::new(self.difficulty, paddle, Rng::from_time()));
+      self.opponent = Opponent::Cpu(ais.collect());
+      self.start_game(ctx, self.settings, random_seed())?;
+    } else if input::is_key_pressed(ctx, Key::Num2) {
+      self.opponent = Opponent::Human;
+      self.start_game(ctx, self.settings, random_seed())?;
+    } else if input::is_key_pressed(ctx, Key::O) {
+      self.set_scene(Scene::Options);
+    } else if input::is_key_pressed(ctx, Key::L) {
+      self.leaderboard_text.set_content(leaderboard_content(&self.stats));
+      self.set_scene(Scene::Leaderboard);
+    } else if input::is_key_pressed(ctx, Key::P) {
+      self.next_profile();
+    } else if input::is_key_pressed(ctx, Key::Left) {
+      self.difficulty = self.difficulty.previous();
+      self.refresh_menu();
+    } else if input::is_key_pressed(ctx, Key::Right) {
+      self.difficulty = self.difficulty.next();
+      self.refresh_menu();
+    }
     Ok(())
   }
 
-  fn update(&mut self, ctx: &mut Context) -> tetra::Result {
-    /*             Player 1 Controls               */
-    if input::is_key_down(ctx, Key::W) {
-      // Up
-      self.player1.position.y -= PADDLE_SPEED;
+  fn refresh_menu(&mut self) {
+    self.menu_text.set_content(menu_content(self.difficulty, self.settings.mode, &self.stats.profile));
+  }
+
+  /// Switches to the next profile that has stats, on the title and the leaderboard
+  fn next_profile(&mut self) {
+    self.stats.next_profile();
+    self.save_stats();
+    self.refresh_menu();
+    self.leaderboard_text.set_content(leaderboard_content(&self.stats));
+  }
+
+  fn update_options(&mut self, ctx: &mut Context) {
+    let count = MenuOption::ALL.len();
+    let option = MenuOption::ALL[self.selected_option];
+    if input::is_key_pressed(ctx, Key::Up) {
+      self.selected_option = (self.selected_option + count - 1) % count;
+    } else if input::is_key_pressed(ctx, Key::Down) {
+      self.selected_option = (self.selected_option + 1) % count;
+    } else if input::is_key_pressed(ctx, Key::Left) {
+      self.settings.menu_adjust(option, -1);
+      self.file_settings.menu_copy(option, &self.settings);
+    } else if input::is_key_pressed(ctx, Key::Right) {
+      self.settings.menu_adjust(option, 1);
+      self.file_settings.menu_copy(option, &self.settings);
+    } else if input::is_key_pressed(ctx, Key::C) {
+      self.set_scene(Scene::Controls);
+      return;
+    } else if input::is_key_pressed(ctx, Key::T) {
+      self.save_settings();
+      self.refresh_menu();
+      self.set_scene(Scene::Title);
+      return;
+    } else {
+      // Laying the text out again every tick adds up, only do it when something changed
+      return;
     }
+    self.options_text.set_content(options_content(&self.settings, self.selected_option));
+    self.audio.set_volume(ctx, &self.settings);
+  }
 
-    if input::is_key_down(ctx, Key::S) {
-      // Down
-      self.player1.position.y += PADDLE_SPEED;
+  fn save_settings(&self) {
+    // Not being able to save isn't a reason to stop playing,
+    // the settings still last until the game is closed
+    if let Some(path) = &self.settings_path {
+      if let Err(err) = self.file_settings.save(path) {
+        eprintln!("Couldn't save settings to {}: {}", path.display(), err);
+      }
     }
+  }
 
-    // Make sure the paddle doesn't go off the screen.
-    if self.player1.position.y > WINDOW_HEIGHT - self.player1.texture.height() as f32 {
-      self.player1.position.y = WINDOW_HEIGHT - self.player1.texture.height() as f32;
-    } else if self.player1.position.y < 0 as f32 {
-      self.player1.position.y = 0 as f32;
+  fn save_stats(&self) {
+    if let Some(path) = &self.stats_path {
+      if let Err(err) = self.stats.save(path) {
+        eprintln!("Couldn't save stats to {}: {}", path.display(), err);
+      }
     }
+  }
+
+  /// Counts a game that was played to the end towards the current profile.
+  /// Whoever is at this keyboard is player 1, or whichever side they have in a network game.
+  fn finish_stats(&mut self, winner: Player) {
+    let local = match &self.opponent {
+      Opponent::Remote(session) => session.local_player(),
+      _ => Player::One,
+    };
+    self.stats.finish(&self.tally, winner == local, self.simulation.elapsed);
+    self.save_stats();
+  }
 
-    /*             Player 2 Controls               */
-    if input::is_key_down(ctx, Key::Up) {
-      // Up
-      self.player2.position.y -= PADDLE_SPEED;
+  /// The controls screen lists every action and then the gamepad deadzone
+  fn update_controls(&mut self, ctx: &mut Context) {
+    let count = ACTIONS.len() + 1;
+    let action = ACTIONS.get(self.selected_control).copied();
+
+    if self.rebinding {
+      // Whatever key comes next is the new binding, unless it can't be bound
+      let pressed: Vec<Key> = input::get_keys_pressed(ctx).copied().collect();
+      let bound = match (action, pressed.first()) {
+        (Some(action), Some(&key)) => self.controls.bind(action, key),
+        _ => false,
+      };
+      if !bound {
+        return;
+      }
+      self.rebinding = false;
+    } else if input::is_key_pressed(ctx, Key::Up) {
+      self.selected_control = (self.selected_control + count - 1) % count;
+    } else if input::is_key_pressed(ctx, Key::Down) {
+      self.selected_control = (self.selected_control + 1) % count;
+    } else if input::is_key_pressed(ctx, Key::Enter) && action.is_some() {
+      self.rebinding = true;
+    } else if input::is_key_pressed(ctx, Key::Left) && action.is_none() {
+      self.controls.adjust_deadzone(-1);
+    } else if input::is_key_pressed(ctx, Key::Right) && action.is_none() {
+      self.controls.adjust_deadzone(1);
+    } else if input::is_key_pressed(ctx, Key::T) {
+      if let Some(path) = &self.controls_path {
+        if let Err(err) = self.controls.save(path) {
+          eprintln!("Couldn't save controls to {}: {}", path.display(), err);
+        }
+      }
+      self.set_scene(Scene::Options);
+      return;
+    } else {
+      // Same as the options, the text only changes after a key press
+      return;
     }
+    self.controls_text.set_content(controls_content(&self.controls, self.selected_control, self.rebinding));
+  }
 
-    if input::is_key_down(ctx, Key::Down) {
-      // Down
-      self.player2.position.y += PADDLE_SPEED;
+  /// M mutes, - and = turn the volume down and up. These work on every screen.
+  fn update_volume(&mut self, ctx: &mut Context) {
+    if input::is_key_pressed(ctx, controls::MUTE) {
+      self.settings.muted = !self.settings.muted;
+    } else if input::is_key_pressed(ctx, controls::VOLUME_DOWN) {
+      self.settings.change_volume(-0.1);
+    } else if input::is_key_pressed(ctx, controls::VOLUME_UP) {
+      self.settings.change_volume(0.1);
+    } else {
+      return;
     }
+    self.file_settings.muted = self.settings.muted;
+    self.file_settings.volume = self.settings.volume;
+    self.audio.set_volume(ctx, &self.settings);
+    self.options_text.set_content(options_content(&self.settings, self.selected_option));
+    self.save_settings();
+  }
 
-    // Make sure the paddle doesn't go off the screen.
-    if self.player2.position.y > WINDOW_HEIGHT - self.player2.texture.height() as f32 {
-      self.player2.position.y = WINDOW_HEIGHT - self.player2.texture.height() as f32;
-    } else if self.player2.position.y < 0 as f32 {
-      self.player2.position.y = 0 as f32;
+  /// F11 switches between a window and fullscreen, on every screen
+  fn update_window(&mut self, ctx: &mut Context) -> tetra::Result {
+    if !input::is_key_pressed(ctx, controls::FULLSCREEN) {
+      return Ok(());
     }
+    let fullscreen = !window::is_fullscreen(ctx);
+    window::set_fullscreen(ctx, fullscreen)?;
+    let (width, height) = window::get_size(ctx);
+    self.scaler.set_outer_size(width, height);
+    self.settings.fullscreen = fullscreen;
+    self.file_settings.fullscreen = fullscreen;
+    self.save_settings();
+    Ok(())
+  }
+
+  /// Leaves whatever game is going on, which also hangs up on a network opponent
+  fn back_to_title(&mut self) {
+    self.save_recording();
+    self.opponent = Opponent::Human;
+    self.set_scene(Scene::Title);
+  }
 
-    /*             Ball Controls               */
-    let player1_bounds = self.player1.bounds();
-    let player2_bounds = self.player2.bounds();
-    let ball_bounds = self.ball.bounds();
+  fn update_connecting(&mut self, ctx: &mut Context) -> tetra::Result {
+    let Opponent::Remote(session) = &mut self.opponent else {
+      return Ok(());
+    };
+    session.poll();
+    if session.status() != Status::Connected {
+      return Ok(());
+    }
+
+    // Everyone plays by the host's rules
+    let seed = session.seed();
+    let rules = std::str::from_utf8(session.rules()).map_err(|err| err.to_string());
+    let settings = rules.and_then(|rules| Settings::from_toml(rules).map_err(|err| err.to_string()));
+    // Only two players can take part over the network
+    let settings = settings.and_then(|settings| match settings.mode {
+      Mode::Classic => Ok(settings),
+      mode => Err(format!("{} can't be played over the network", mode.name())),
+    });
+    match settings {
+      Ok(settings) => {
+        self.start_game(ctx, settings, seed)?;
+        // Nobody can agree on when to serve, so the ball just goes
+        self.set_scene(Scene::Playing);
+      },
+      Err(err) => {
+        eprintln!("Couldn't understand the host's settings: {}", err);
+        self.set_scene(Scene::Disconnected);
+      },
+    }
+    Ok(())
+  }
+
+  fn update_playing(&mut self, ctx: &mut Context) {
+    // You can't pause the other player's game
+    let pause = self.controls.pressed(ctx, Action::Pause) || self.controls.pressed(ctx, Action::Serve);
+    if pause && !self.networked() {
+      self.set_scene(Scene::Paused);
+      return;
+    }
+
+    // All the game logic lives in the simulation,
+    // all we have to do is tell it how each player wants to move.
+    // Each paddle is moved with the controls of the player with the same number,
+    // so in doubles players 3 and 4 have the front paddles.
+    let controls = &self.controls;
+    let sim = &self.simulation;
+    let human = |index: usize| match sim.paddles.get(index) {
+      Some(paddle) => controls.movement(ctx, Player::from_index(index), paddle.is_vertical()),
+      None => 0.0,
+    };
+    // With only one person playing, either of the first two sets of controls works
+    let either = || {
+      let vertical = sim.paddles[0].is_vertical();
+      (controls.movement(ctx, Player::One, vertical) + controls.movement(ctx, Player::Two, vertical)).clamp(-1.0, 1.0)
+    };
+    let inputs = match &mut self.opponent {
+      Opponent::Human => Inputs(std::array::from_fn(human)),
+      Opponent::Cpu(ais) => {
+        let mut inputs = [0.0; MAX_PADDLES];
+        inputs[0] = either();
+        for ai in ais.iter_mut() {
+          inputs[ai.paddle()] = ai.input(sim, TIMESTEP);
+        }
+        Inputs(inputs)
+      },
+      Opponent::Remote(session) => {
+        session.add_local_input(either());
+        session.poll();
+        match session.next_inputs() {
+          Some(inputs) => inputs,
+          // Inputs that arrived before the other player left still get played,
+          // they might be the ones that end the game
+          None if session.status() == Status::Disconnected => {
+            self.save_recording();
+            self.set_scene(Scene::Disconnected);
+            return;
+          },
+          None => {
+            // Still waiting for the other player's input for this tick,
+            // everything stays where it is until it arrives
+            self.previous = self.simulation.clone();
+            return;
+          },
+        }
+      },
+    };
+    // Stepping with exactly what gets recorded is what makes replays match
+    let inputs = inputs.quantize();
+    if let Some(recording) = &mut self.recording {
+      recording.push(inputs);
+    }
+    self.previous = self.simulation.clone();
+    let events = self.simulation.step(Inputs::dequantize(inputs), TIMESTEP);
+    self.audio.play(ctx, &events, &self.simulation);
+    if self.settings.effects {
+      self.visuals.react(&self.previous, &self.simulation, &events);
+    }
+    self.tally.update(&events, &self.simulation);
+
+    for event in events {
+      match event {
+        // After a point everything jumps back to the middle,
+        // don't draw the ball sliding there
+        Event::Scored(_) | Event::LostLife(_) => {
+          self.previous = self.simulation.clone();
+          if !self.networked() {
+            self.set_scene(Scene::Serve);
+          }
+        },
+        // Always comes after the point, so the game ends instead of serving again
+        Event::Won(winner) => {
+          self.save_recording();
+          self.finish_stats(winner);
+          self.set_scene(Scene::GameOver(winner));
+        },
+        _ => {},
+      }
+    }
+  }
+
+  /// Back to the start of the replay being watched
+  fn rewind(&mut self) {
+    let Some(playback) = &mut self.playback else {
+      return;
+    };
+    playback.tick = 0;
+    self.simulation = playback.replay.simulation();
+    self.previous = self.simulation.clone();
+    self.visuals.clear();
+    self.scene = Scene::Playing;
+    self.message.set_content("");
+  }
+
+  fn update_playback(&mut self, ctx: &mut Context) {
+    let Some(playback) = &mut self.playback else {
+      return;
+    };
 
-    let paddle_hit = if ball_bounds.intersects(&player1_bounds) {
-      Some(&self.player1)
-    } else if ball_bounds.intersects(&player2_bounds) {
-      Some(&self.player2)
+    if input::is_key_pressed(ctx, Key::R) {
+      self.rewind();
+      return;
+    }
+    // What the message says right now, it only needs changing if this does
+    let shown = (playback.tick == playback.replay.len(), playback.paused);
+    if input::is_key_pressed(ctx, Key::P) || input::is_key_pressed(ctx, Key::Space) {
+      playback.paused = !playback.paused;
+      self.audio.pause_music(playback.paused);
+    }
+
+    // Right steps one tick at a time while paused, holding F plays 4x as fast
+    let ticks = if playback.paused {
+      input::is_key_pressed(ctx, Key::Right) as usize
+    } else if input::is_key_down(ctx, Key::F) {
+      4
     } else {
-      None
+      1
     };
 
-    if let Some(paddle) = paddle_hit {
-      // Increase the ball's velocity, then flip it.
-      self.ball.velocity.x = 
-        -(self.ball.velocity.x + (BALL_ACC * self.ball.velocity.x.signum()));
-      
-      // Calculat ethe offset between the paddle and the ball, as a number
-      // between -1 and 1.
-      let offset = (paddle.center().y - self.ball.center().y) / paddle.height();
+    self.previous = self.simulation.clone();
+    for _ in 0..ticks {
+      let Some(inputs) = playback.replay.inputs(playback.tick) else {
+        break;
+      };
+      playback.tick += 1;
+      self.previous = self.simulation.clone();
+      let events = self.simulation.step(inputs, TIMESTEP);
+      self.audio.play(ctx, &events, &self.simulation);
+      if self.settings.effects {
+        self.visuals.react(&self.previous, &self.simulation, &events);
+      }
+      if events.iter().any(|event| matches!(event, Event::Scored(_) | Event::LostLife(_))) {
+        self.previous = self.simulation.clone();
+      }
+    }
+
+    let ended = playback.tick == playback.replay.len();
+    if (ended, playback.paused) != shown {
+      let message = if ended {
+        "End of replay\n\nR - Watch Again"
+      } else if playback.paused {
+        "Paused\n\nSpace - Play\nRight - Step\nR - Restart"
+      } else {
+        ""
+      };
+      self.message.set_content(message);
+    }
+  }
+}
+
+impl Drop for GameState {
+  // Closing the window in the middle of a game still saves its replay
+  fn drop(&mut self) {
+    self.save_recording();
+  }
+}
+
+/// Scales a field the size in `settings` up or down to fit the window, keeping its shape
+fn field_scaler(ctx: &mut Context, settings: &Settings) -> tetra::Result<ScreenScaler> {
+  let (width, height) = (settings.window_width as i32, settings.window_height as i32);
+  ScreenScaler::with_window_size(ctx, width, height, ScalingMode::ShowAll)
+}
+
+fn menu_content(difficulty: Difficulty, mode: Mode, profile: &str) -> String {
+  let players = match mode.paddles() {
+    2 => "Two",
+    3 => "Three",
+    _ => "Four",
+  };
+  format!(
+    "{}\n\n1 - One Player (vs CPU)\n2 - {} Players\nO - Options\nL - Leaderboard\n\n< CPU Difficulty: {} >\nP - Profile: {}",
+    mode.name(),
+    players,
+    difficulty.name(),
+    profile
+  )
+}
+
+fn leaderboard_content(stats: &Stats) -> String {
+  let mut content = String::from("Leaderboard\n\n");
+  let leaders = stats.leaderboard();
+  if leaders.is_empty() {
+    content += "Nobody has finished a game yet\n";
+  }
+  for (rank, (name, record)) in leaders.iter().enumerate() {
+    let marker = if *name == stats.profile { "> " } else { "   " };
+    content += &format!(
+      "{}{}. {}   {} - {}   Rally {}   Speed {}\n",
+      marker,
+      rank + 1,
+      name,
+      record.wins,
+      record.losses,
+      record.longest_rally,
+      record.fastest_ball.round()
+    );
+  }
+
+  // Wins and losses are on the board, the rest is only shown for the current profile
+  let record = stats.record();
+  let longest = record.longest_match.round() as u32;
+  let played = record.time_played.round() as u32;
+  content += &format!(
+    "\n{}: Longest Match {}:{:02}, Played {}:{:02}\n",
+    stats.profile,
+    longest / 60,
+    longest % 60,
+    played / 60,
+    played % 60
+  );
+  content + "\nP - Change Profile\nT - Title"
+}
+
+/// Points for two sides, lives for more
+fn score_content(sim: &Simulation) -> String {
+  if !sim.mode().elimination() {
+    return format!("{} - {}", sim.scores[0], sim.scores[1]);
+  }
+  let lives: Vec<String> = sim.players().map(|player| format!("P{}: {}", player.index() + 1, sim.score(player))).collect();
+  lives.join("    ")
+}
+
+fn options_content(settings: &Settings, selected: usize) -> String {
+  let mut content = String::new();
+  for (index, &option) in MenuOption::ALL.iter().enumerate() {
+    let marker = if index == selected { "> " } else { "   " };
+    content += &format!("{}{}\n", marker, settings.menu_label(option));
+  }
+  content + "\nUp/Down - Choose, Left/Right - Change\nC - Controls\nT - Save and go back"
+}
+
+fn controls_content(controls: &Controls, selected: usize, rebinding: bool) -> String {
+  let mut content = String::new();
+  for (index, &action) in ACTIONS.iter().enumerate() {
+    let marker = if index == selected { "> " } else { "   " };
+    let key = if rebinding && index == selected { "Press a key..." } else { controls.key_label(action) };
+    content += &format!("{}{}: {}\n", marker, action.name(), key);
+  }
+  let marker = if selected == ACTIONS.len() { "> " } else { "   " };
+  content += &format!("{}Gamepad Deadzone: {}%\n", marker, (controls.deadzone * 100.0).round());
+  content + "\nUp/Down - Choose, Enter - Rebind\nLeft/Right - Change\nT - Save and go back"
+}
+
+impl GameState {
+  /// Draws whatever screen we're on, onto the canvas
+  fn draw_scene(&mut self, ctx: &mut Context) {
+    graphics::clear(ctx, Color::rgb(0.392, 0.584, 0.929));
+
+    // Everything is drawn at the size of the current game's field, and scaled to the window after
+    let width = self.simulation.settings.window_width;
+    let height = self.simulation.settings.window_height;
+
+    match self.scene {
+      Scene::Connecting => {
+        draw_centered(ctx, &mut self.message, width / 2.0, height / 3.0);
+        return;
+      },
+      Scene::Title => {
+        draw_centered(ctx, &mut self.menu_text, width / 2.0, height / 3.0);
+        return;
+      },
+      Scene::Options => {
+        // Starts higher up than the other menus, it's the longest one
+        self.options_text.draw(ctx, Vec2::new(width / 8.0, height / 16.0));
+        return;
+      },
+      Scene::Controls => {
+        self.controls_text.draw(ctx, Vec2::new(width / 8.0, height / 8.0));
+        return;
+      },
+      Scene::Leaderboard => {
+        self.leaderboard_text.draw(ctx, Vec2::new(width / 16.0, height / 8.0));
+        return;
+      },
+      _ => {},
+    }
+
+    let sim = &self.simulation;
+    let previous = &self.previous;
+    // Nothing moves outside of Playing, so there's nothing to smooth out
+    let blend = if self.scene == Scene::Playing { time::get_blend_factor(ctx) } else { 1.0 };
+
+    // Trails and sparks go underneath everything else
+    if self.settings.effects {
+      self.visuals.draw(ctx, &self.resources, sim);
+    }
+
+    // Draws the paddles to the screen at their positions.
+    // According to the docs, the second parameter is of the type
+    // Into<DrawParams>, but Vec2 is automatically converted to this type.
+    let textures = &self.resources;
+    for (index, (before, paddle)) in previous.paddles.iter().zip(&sim.paddles).enumerate() {
+      // Knocked out players' sides are walls now
+      if !paddle.player().is_some_and(|player| sim.in_play(player)) {
+        continue;
+      }
+      let texture = if index % 2 == 0 { &textures.player1 } else { &textures.player2 };
+      texture.draw(ctx, paddle_params(texture, before, paddle, blend).color(player_color(paddle.player())));
+    }
+    for power_up in &sim.power_ups {
+      if let EntityType::PowerUp(kind) = power_up.entity_type {
+        let params = DrawParams::new().position(Vec2::from(power_up.position)).color(power_up_color(kind));
+        textures.power_up.draw(ctx, params);
+      }
+    }
+    if sim.winner.is_none() {
+      for (index, ball) in sim.balls.iter().enumerate() {
+        // A ball that just split off multi-ball wasn't there last step
+        let before = previous.balls.get(index).unwrap_or(ball);
+        textures.ball.draw(ctx, interpolate(before.position, ball.position, blend));
+      }
+    }
+
+    // Draw the score. Changing a Text's content means laying it out again,
+    // so only do that when someone scores.
+    if sim.scores != self.shown_score {
+      self.score.set_content(score_content(sim));
+      self.shown_score = sim.scores.clone();
+    }
+    // Keep the text clear of the paddles on the top and bottom
+    let top = if sim.mode().players() > 2 { 48.0 } else { 16.0 };
+    let bottom = if sim.mode().players() > 3 { height - 80.0 } else { height - 40.0 };
+    draw_centered(ctx, &mut self.score, width / 2.0, top);
 
-      // Apply the spin to the ball
-      self.ball.velocity.y += PADDLE_SPIN * -offset;
+    if let Some(left) = sim.time_left() {
+      let seconds = left.ceil() as u32;
+      if self.shown_time != Some(seconds) {
+        self.clock.set_content(format!("{}:{:02}", seconds / 60, seconds % 60));
+        self.shown_time = Some(seconds);
+      }
+      draw_centered(ctx, &mut self.clock, width / 2.0, top + 40.0);
     }
 
-    self.ball.position += self.ball.velocity;
+    let effects: Vec<String> = sim
+      .effects
+      .iter()
+      .map(|effect| format!("{}: {} ({})", effect.player.name(), effect.power_up.name(), effect.remaining.ceil()))
+      .collect();
+    let effects = effects.join("   ");
+    if effects != self.shown_effects {
+      self.effects_text.set_content(effects.as_str());
+      self.shown_effects = effects;
+    }
+    draw_centered(ctx, &mut self.effects_text, width / 2.0, bottom);
+
+    draw_centered(ctx, &mut self.message, width / 2.0, height / 3.0);
+  }
+}
+
+impl State for GameState {
+  fn draw(&mut self, ctx: &mut Context) -> tetra::Result {
+    graphics::set_canvas(ctx, &self.canvas);
+    self.draw_scene(ctx);
 
-    if self.ball.position.y <= 0.0 
-      || self.ball.position.y + self.ball.height() >= WINDOW_HEIGHT {
-        self.ball.velocity.y = -self.ball.velocity.y;
+    // Shaking shows a bit of the background around the edges
+    graphics::set_canvas(ctx, self.scaler.canvas());
+    graphics::clear(ctx, Color::BLACK);
+    let offset = if self.settings.effects { self.visuals.shake_offset() } else { Vec2::zero() };
+    if self.settings.post_processing {
+      graphics::set_shader(ctx, &self.resources.post_process);
     }
+    self.canvas.draw(ctx, offset);
+    graphics::reset_shader(ctx);
+    graphics::reset_canvas(ctx);
+
+    // The bars around the field, when the window is a different shape
+    graphics::clear(ctx, Color::BLACK);
+    self.scaler.draw(ctx);
 
-    /*        Score Counting          */
-    if self.ball.position.x < 0.0 {
-      self.player2.score += 1;
-      self.player1.reset();
-      self.player2.reset();
-      self.ball.reset();
+    Ok(())
+  }
+
+  fn event(&mut self, _ctx: &mut Context, event: tetra::Event) -> tetra::Result {
+    // The field is still the same size, it's just drawn bigger or smaller
+    if let tetra::Event::Resized { width, height } = event {
+      self.scaler.set_outer_size(width, height);
     }
+    Ok(())
+  }
 
-    if self.ball.position.x > WINDOW_WIDTH {
-      self.player1.score += 1;
-      self.player1.reset();
-      self.player2.reset();
-      self.ball.reset();
+  fn update(&mut self, ctx: &mut Context) -> tetra::Result {
+    self.visuals.update();
+    self.update_window(ctx)?;
+
+    // The key being bound shouldn't also change the volume
+    if !self.rebinding {
+      self.update_volume(ctx);
     }
 
-    if self.player1.score == WINNING_SCORE {
-      self.winner = String::from("Player 1");
-    } else if self.player2.score == WINNING_SCORE {
-      self.winner = String::from("Player 2");
+    if self.playback.is_some() {
+      self.update_playback(ctx);
+      return Ok(());
+    }
+
+    match self.scene {
+      Scene::Title => self.update_title(ctx)?,
+      Scene::Options => self.update_options(ctx),
+      Scene::Controls => self.update_controls(ctx),
+      Scene::Connecting => self.update_connecting(ctx)?,
+      Scene::Serve => {
+        if self.controls.pressed(ctx, Action::Serve) {
+          self.set_scene(Scene::Playing);
+        }
+      },
+      Scene::Playing => self.update_playing(ctx),
+      Scene::Paused => {
+        if self.controls.pressed(ctx, Action::Pause) || self.controls.pressed(ctx, Action::Serve) {
+          self.set_scene(Scene::Playing);
+        }
+      },
+      Scene::Leaderboard => {
+        if input::is_key_pressed(ctx, Key::P) {
+          self.next_profile();
+        }
+      },
+      Scene::GameOver(_) => {
+        // The other player may not have our last inputs yet, and can't see
+        // the game end without them, so keep sending until they confirm
+        if let Opponent::Remote(session) = &mut self.opponent {
+          session.poll();
+        }
+      },
+      Scene::Disconnected => {},
+    }
+
+    // Anywhere outside of a game can go back to the title, and local games can start over
+    let can_restart = matches!(self.scene, Scene::Paused | Scene::GameOver(_)) && !self.networked();
+    let can_leave = matches!(
+      self.scene,
+      Scene::Leaderboard | Scene::Connecting | Scene::Paused | Scene::GameOver(_) | Scene::Disconnected
+    );
+    if can_restart && input::is_key_pressed(ctx, controls::RESTART) {
+      self.start_game(ctx, self.simulation.settings, random_seed())?;
+    } else if can_leave && input::is_key_pressed(ctx, controls::TO_TITLE) {
+      self.back_to_title();
     }
 
     Ok(())
   }
 }
 
+/// `--host [PORT]` waits for someone to join over the network,
+/// `--join ADDRESS` joins them and `--replay FILE` watches a saved game.
+/// Without any of them it's a local game.
+/// `--profile NAME` picks whose stats the games count towards.
+/// Any setting can be overridden too, like `--ball-speed 400`.
+fn parse_args(args: &[String], settings: &mut Settings, stats: &mut Stats) -> Result<Start, String> {
+  let mut host = None;
+  let mut join = None;
+  let mut replay = None;
+
+  let mut args = args.iter().skip(1).peekable();
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--host" => {
+        // The port is optional
+        let port = match args.next_if(|next| !next.starts_with("--")) {
+          Some(port) => port.parse().map_err(|_| format!("invalid port: {}", port))?,
+          None => net::DEFAULT_PORT,
+        };
+        host = Some(port);
+      },
+      "--join" => {
+        let address = args.next().ok_or("--join needs an address to join, like 192.168.1.2:7777")?;
+        join = Some(address);
+      },
+      "--replay" => {
+        let path = args.next().ok_or("--replay needs a replay file to watch")?;
+        replay = Some(path);
+      },
+      "--profile" => {
+        let name = args.next().ok_or("--profile needs a name")?;
+        stats.select(name)?;
+      },
+      _ => {
+        let name = arg.strip_prefix("--").ok_or_else(|| format!("unknown argument: {}", arg))?;
+        let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+        settings.set(name, value)?;
+      },
+    }
+  }
+
+  match (host, join, replay) {
+    (None, None, None) => Ok(Start::Local),
+    (Some(port), None, None) => {
+      if settings.mode != Mode::Classic {
+        return Err(String::from("network games can only be played in classic mode"));
+      }
+      let rules = settings.to_toml().map_err(|err| err.to_string())?;
+      let session = Session::host(port, random_seed(), rules.into_bytes());
+      session.map(Start::Network).map_err(|err| format!("couldn't host on port {}: {}", port, err))
+    },
+    (None, Some(address), None) => {
+      Session::join(address).map(Start::Network).map_err(|err| format!("couldn't join {}: {}", address, err))
+    },
+    (None, None, Some(path)) => {
+      let replay = Replay::load(path.as_ref()).map_err(|err| format!("couldn't load replay {}: {}", path, err))?;
+      Ok(Start::Replay(replay))
+    },
+    _ => Err(String::from("only one of --host, --join and --replay can be used at a time")),
+  }
+}
+
 fn main() -> tetra::Result {
-    ContextBuilder::new("Pong", WINDOW_WIDTH as i32, WINDOW_HEIGHT as i32)
+    // A broken settings file shouldn't stop anyone from playing
+    let settings_path = Settings::path();
+    let mut settings = match &settings_path {
+      Some(path) => Settings::load(path).unwrap_or_else(|err| {
+        eprintln!("Couldn't load settings from {}, using the defaults: {}", path.display(), err);
+        Settings::default()
+      }),
+      None => Settings::default(),
+    };
+
+    let controls_path = Controls::path();
+    let controls = match &controls_path {
+      Some(path) => Controls::load(path).unwrap_or_else(|err| {
+        eprintln!("Couldn't load controls from {}, using the defaults: {}", path.display(), err);
+        Controls::default()
+      }),
+      None => Controls::default(),
+    };
+
+    let stats_path = Stats::path();
+    let mut stats = match &stats_path {
+      Some(path) => Stats::load(path).unwrap_or_else(|err| {
+        eprintln!("Couldn't load stats from {}, starting over: {}", path.display(), err);
+        Stats::default()
+      }),
+      None => Stats::default(),
+    };
+
+    // Whatever's on the command line is only for this run
+    let file_settings = settings;
+    let args: Vec<String> = env::args().collect();
+    let start = parse_args(&args, &mut settings, &mut stats).unwrap_or_else(|err| {
+      eprintln!("Problem parsing arguments: {}", err);
+      process::exit(1);
+    });
+    let saved = Saved { settings: file_settings, settings_path, controls, controls_path, stats, stats_path };
+    let field = start.field_settings(settings);
+
+    ContextBuilder::new("Pong", field.window_width as i32, field.window_height as i32)
       .quit_on_escape(true)
+      // The window can be any size, the field is scaled to fit it
+      .resizable(true)
+      .fullscreen(settings.fullscreen)
+      // `update` is called exactly this many times per second (catching up if a
+      // frame was slow), while `draw` runs as often as the screen refreshes
+      .timestep(Timestep::Fixed(TICKS_PER_SECOND))
       .build()?
-      // Because our constructor function's signature and the run closure's
-      // signature are the same, we can use GameState::new directly.
-      .run(GameState::new)
+      // The closure hands everything from the command line to the constructor
+      .run(|ctx| GameState::new(ctx, settings, saved, start))
 }
@@ -0,0 +1,114 @@
+// The float functions the simulation needs, written so they give exactly the
+// same answer on every machine. `+`, `-`, `*`, `/` and `sqrt` are always
+// rounded the same way, but `sin`, `atan2` and friends come from whatever
+// math library the platform has, and those are allowed to differ in the last
+// bit. That's enough for two network players (or a replay) to drift apart,
+// so these only use the operations that can't.
+
+use std::f32::consts::{FRAC_PI_2, PI};
+
+/// `x.sin()`, the same everywhere
+pub fn sin(x: f32) -> f32 {
+  let (quarter, r) = quarter_turns(x);
+  match quarter {
+    0 => sin_near_zero(r),
+    1 => cos_near_zero(r),
+    2 => -sin_near_zero(r),
+    _ => -cos_near_zero(r),
+  }
+}
+
+/// `x.cos()`, the same everywhere
+pub fn cos(x: f32) -> f32 {
+  let (quarter, r) = quarter_turns(x);
+  match quarter {
+    0 => cos_near_zero(r),
+    1 => -sin_near_zero(r),
+    2 => -cos_near_zero(r),
+    _ => sin_near_zero(r),
+  }
+}
+
+/// `y.atan2(x)`, the same everywhere
+pub fn atan2(y: f32, x: f32) -> f32 {
+  let (ax, ay) = (x.abs(), y.abs());
+  if ax == 0.0 && ay == 0.0 {
+    return 0.0;
+  }
+  // Always divide the smaller by the bigger, so atan only sees -1 to 1
+  let angle = if ay <= ax { atan(ay / ax) } else { FRAC_PI_2 - atan(ax / ay) };
+  let angle = if x < 0.0 { PI - angle } else { angle };
+  if y < 0.0 { -angle } else { angle }
+}
+
+/// `x.hypot(y)`, the same everywhere
+pub fn hypot(x: f32, y: f32) -> f32 {
+  (x * x + y * y).sqrt()
+}
+
+/// Splits `x` into how many quarter turns it is (0 to 3) and what's left over,
+/// which is between -π/4 and π/4 where the series below are accurate
+fn quarter_turns(x: f32) -> (i64, f32) {
+  let turns = (x / FRAC_PI_2).round();
+  let r = x - turns * FRAC_PI_2;
+  ((turns as i64).rem_euclid(4), r)
+}
+
+// Taylor series, plenty of terms for f32 between -π/4 and π/4
+
+fn sin_near_zero(x: f32) -> f32 {
+  let x2 = x * x;
+  x * (1.0 - x2 / 6.0 * (1.0 - x2 / 20.0 * (1.0 - x2 / 42.0 * (1.0 - x2 / 72.0))))
+}
+
+fn cos_near_zero(x: f32) -> f32 {
+  let x2 = x * x;
+  1.0 - x2 / 2.0 * (1.0 - x2 / 12.0 * (1.0 - x2 / 30.0 * (1.0 - x2 / 56.0)))
+}
+
+/// Only for -1 to 1
+fn atan(x: f32) -> f32 {
+  // atan(x) = 2 atan(x / (1 + sqrt(1 + x²))), twice gets x under 0.2,
+  // where the series needs few terms
+  let mut x = x;
+  for _ in 0..2 {
+    x /= 1.0 + (1.0 + x * x).sqrt();
+  }
+  // 1 - x²/3 + x⁴/5 - ..., added up from the smallest term
+  let x2 = x * x;
+  let mut sum = 0.0;
+  for n in (0..8).rev() {
+    let sign = if n % 2 == 0 { 1.0 } else { -1.0 };
+    sum = sign / (2 * n + 1) as f32 + x2 * sum;
+  }
+  // Undo the two halvings
+  4.0 * x * sum
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn close_to_the_standard_library() {
+    for i in -400..=400 {
+      let x = i as f32 / 100.0;
+      assert!((sin(x) - x.sin()).abs() < 1e-6, "sin({})", x);
+      assert!((cos(x) - x.cos()).abs() < 1e-6, "cos({})", x);
+      for j in [-3.0, -0.5, 0.0, 0.25, 2.0] {
+        assert!((atan2(x, j) - x.atan2(j)).abs() < 1e-6, "atan2({}, {})", x, j);
+      }
+    }
+    assert_eq!(5.0, hypot(3.0, -4.0));
+    assert_eq!(0.0, atan2(0.0, 0.0));
+  }
+
+  #[test]
+  fn exactly_the_same_bits() {
+    // Worked out once, these must never change: every replay and network game depends on them
+    assert_eq!(0x3ef57744, sin(0.5).to_bits());
+    assert_eq!(0xbf76abbf, sin(-1.3).to_bits());
+    assert_eq!(0x3eb986f5, cos(1.2).to_bits());
+    assert_eq!(0x402b6374, atan2(1.0, -2.0).to_bits());
+  }
+}
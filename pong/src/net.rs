@@ -0,0 +1,489 @@
+// Playing over the network, one machine hosts and the other joins.
+//
+// Both machines run the whole simulation themselves and only send each
+// other their inputs (lockstep). A tick is only simulated once both
+// inputs for it have arrived, so the two games can never drift apart.
+// To hide the time that takes, local inputs are scheduled a few ticks
+// into the future (input delay), and every packet repeats all the inputs
+// the other side hasn't confirmed yet, so a lost packet doesn't matter.
+// The host's rules and random seed are sent to whoever joins, so both
+// play the same game.
+
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::simulation::{dequantize_input, quantize_input, Inputs, Player};
+
+pub const DEFAULT_PORT: u16 = 7777;
+
+// 3 ticks is 50ms, plenty for a LAN and most connections nearby
+const INPUT_DELAY: usize = 3;
+// Give up on the other player after hearing nothing for this long
+const TIMEOUT: Duration = Duration::from_secs(3);
+// Keeps packets small even when a lot of inputs haven't been confirmed
+const MAX_INPUTS_PER_PACKET: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+  // Nobody to play with yet
+  Waiting,
+  Connected,
+  Disconnected,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Packet {
+  // Sent by the joining player until the host answers
+  Hello,
+  // The host's answer, with the game's random seed and rules
+  Welcome { seed: u64, rules: Vec<u8> },
+  Inputs {
+    // The sender has every one of our inputs before this tick
+    ack: u32,
+    // The tick of the first input
+    first: u32,
+    inputs: Vec<i8>,
+  },
+  // Sent when a player leaves, so the other one doesn't have to wait for the timeout
+  Bye,
+}
+
+impl Packet {
+  // Every packet is tagged so stray packets from something else are ignored
+  const MAGIC: &'static [u8; 4] = b"PONG";
+
+  fn encode(&self) -> Vec<u8> {
+    let mut bytes = Self::MAGIC.to_vec();
+    match self {
+      Packet::Hello => bytes.push(0),
+      Packet::Inputs { ack, first, inputs } => {
+        bytes.push(1);
+        bytes.extend_from_slice(&ack.to_le_bytes());
+        bytes.extend_from_slice(&first.to_le_bytes());
+        bytes.extend(inputs.iter().map(|&input| input as u8));
+      },
+      Packet::Bye => bytes.push(2),
+      Packet::Welcome { seed, rules } => {
+        bytes.push(3);
+        bytes.extend_from_slice(&seed.to_le_bytes());
+        bytes.extend_from_slice(rules);
+      },
+    }
+    bytes
+  }
+
+  fn decode(bytes: &[u8]) -> Option<Packet> {
+    let bytes = bytes.strip_prefix(Self::MAGIC)?;
+    let (&kind, rest) = bytes.split_first()?;
+    match kind {
+      0 => Some(Packet::Hello),
+      1 if rest.len() >= 8 => Some(Packet::Inputs {
+        ack: u32::from_le_bytes(rest[0..4].try_into().ok()?),
+        first: u32::from_le_bytes(rest[4..8].try_into().ok()?),
+        inputs: rest[8..].iter().map(|&input| input as i8).collect(),
+      }),
+      2 => Some(Packet::Bye),
+      3 if rest.len() >= 8 => Some(Packet::Welcome {
+        seed: u64::from_le_bytes(rest[0..8].try_into().ok()?),
+        rules: rest[8..].to_vec(),
+      }),
+      _ => None,
+    }
+  }
+}
+
+pub struct Session {
+  socket: UdpSocket,
+  // A host listens on IPv6 and IPv4. Most systems let one IPv6 socket take
+  // both, where they don't (like Windows) IPv4 gets its own socket.
+  ipv4: Option<UdpSocket>,
+  // The host doesn't know who it's playing until someone says hello
+  peer: Option<SocketAddr>,
+  local_player: Player,
+  status: Status,
+  // Whatever the host wants the joining player to know before playing,
+  // empty until the host has welcomed us
+  seed: u64,
+  rules: Vec<u8>,
+  // Every input so far, indexed by tick
+  local_inputs: Vec<i8>,
+  remote_inputs: Vec<i8>,
+  // How many of our inputs the other player has confirmed
+  acked: usize,
+  // The next tick to simulate
+  tick: usize,
+  last_heard: Instant,
+}
+
+impl Session {
+  /// Waits for someone to join on `port`, who'll be sent `seed` and `rules`.
+  /// The host plays on the left.
+  pub fn host(port: u16, seed: u64, rules: Vec<u8>) -> io::Result<Session> {
+    let (socket, ipv4) = match UdpSocket::bind(("::", port)) {
+      Ok(socket) => {
+        // Fails when the IPv6 socket already covers IPv4, which is fine
+        let ipv4 = UdpSocket::bind(("0.0.0.0", socket.local_addr()?.port())).ok();
+        (socket, ipv4)
+      },
+      // No IPv6 on this machine at all
+      Err(_) => (UdpSocket::bind(("0.0.0.0", port))?, None),
+    };
+    if let Some(ipv4) = &ipv4 {
+      ipv4.set_nonblocking(true)?;
+    }
+    let mut session = Session::new(socket, None, Player::One)?;
+    session.ipv4 = ipv4;
+    session.seed = seed;
+    session.rules = rules;
+    Ok(session)
+  }
+
+  /// Joins a game hosted at `address` (like "192.168.1.2:7777") and plays on the right
+  pub fn join(address: &str) -> io::Result<Session> {
+    let peer = address
+      .to_socket_addrs()?
+      .next()
+      .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "address didn't resolve to anything"))?;
+    let any = if peer.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    Session::new(UdpSocket::bind(any)?, Some(peer), Player::Two)
+  }
+
+  fn new(socket: UdpSocket, peer: Option<SocketAddr>, local_player: Player) -> io::Result<Session> {
+    // The game can't sit around waiting for packets
+    socket.set_nonblocking(true)?;
+    Ok(Session {
+      socket,
+      ipv4: None,
+      peer,
+      local_player,
+      status: Status::Waiting,
+      seed: 0,
+      rules: Vec::new(),
+      // Nobody moves for the first few ticks, that's the input delay
+      local_inputs: vec![0; INPUT_DELAY],
+      remote_inputs: vec![0; INPUT_DELAY],
+      acked: INPUT_DELAY,
+      tick: 0,
+      last_heard: Instant::now(),
+    })
+  }
+
+  pub fn local_addr(&self) -> io::Result<SocketAddr> {
+    self.socket.local_addr()
+  }
+
+  pub fn local_player(&self) -> Player {
+    self.local_player
+  }
+
+  pub fn status(&self) -> Status {
+    self.status
+  }
+
+  /// The seed the host sent (or is sending) with its welcome
+  pub fn seed(&self) -> u64 {
+    self.seed
+  }
+
+  /// The rules the host sent (or is sending) with its welcome
+  pub fn rules(&self) -> &[u8] {
+    &self.rules
+  }
+
+  /// Records this tick's local paddle movement. It gets used `INPUT_DELAY`
+  /// ticks from now, and is ignored if we're already that far ahead.
+  pub fn add_local_input(&mut self, movement: f32) {
+    if self.status == Status::Connected && self.local_inputs.len() <= self.tick + INPUT_DELAY {
+      self.local_inputs.push(quantize_input(movement));
+    }
+  }
+
+  /// Reads everything that has arrived and sends our inputs. Call it once per tick.
+  pub fn poll(&mut self) {
+    if self.status == Status::Disconnected {
+      return;
+    }
+
+    let mut buffer = [0; 2048];
+    let mut received = Vec::new();
+    // Stops when there's nothing left to read. Any other error (like the
+    // other end's port being closed) is left for the timeout to deal with.
+    for socket in std::iter::once(&self.socket).chain(&self.ipv4) {
+      while let Ok((len, from)) = socket.recv_from(&mut buffer) {
+        received.push((buffer[..len].to_vec(), from));
+      }
+    }
+    for (bytes, from) in received {
+      self.receive(&bytes, from);
+    }
+
+    if self.status == Status::Connected && self.last_heard.elapsed() > TIMEOUT {
+      self.status = Status::Disconnected;
+      return;
+    }
+
+    let packet = match self.status {
+      Status::Waiting => Packet::Hello,
+      _ => self.inputs_packet(),
+    };
+    self.send(&packet);
+  }
+
+  /// Every input the other player hasn't confirmed yet
+  fn inputs_packet(&self) -> Packet {
+    Packet::Inputs {
+      ack: self.remote_inputs.len() as u32,
+      first: self.acked as u32,
+      inputs: self.local_inputs[self.acked..].iter().take(MAX_INPUTS_PER_PACKET).copied().collect(),
+    }
+  }
+
+  /// The inputs for the next tick, once both players' have arrived
+  pub fn next_inputs(&mut self) -> Option<Inputs> {
+    let local = *self.local_inputs.get(self.tick)?;
+    let remote = *self.remote_inputs.get(self.tick)?;
+    self.tick += 1;
+
+    // Network games are always two players, the host is player 1
+    let (player1, player2) = if self.local_player == Player::One { (local, remote) } else { (remote, local) };
+    Some(Inputs::two(dequantize_input(player1), dequantize_input(player2)))
+  }
+
+  fn receive(&mut self, bytes: &[u8], from: SocketAddr) {
+    let Some(packet) = Packet::decode(bytes) else {
+      return;
+    };
+    match self.peer {
+      Some(peer) if peer != from => return,
+      // Whoever says hello first gets to play
+      None if packet == Packet::Hello => self.peer = Some(from),
+      None => return,
+      _ => {},
+    }
+
+    // The joining player has to wait for the welcome
+    let joining = self.local_player == Player::Two && self.status == Status::Waiting;
+    if joining && !matches!(packet, Packet::Welcome { .. }) {
+      return;
+    }
+
+    self.last_heard = Instant::now();
+    if self.status == Status::Waiting {
+      self.status = Status::Connected;
+    }
+
+    match packet {
+      // Answer every hello, in case a welcome got lost
+      Packet::Hello => self.send(&Packet::Welcome { seed: self.seed, rules: self.rules.clone() }),
+      Packet::Welcome { seed, rules } => {
+        self.seed = seed;
+        self.rules = rules;
+      },
+      Packet::Inputs { ack, first, inputs } => {
+        self.acked = self.acked.max(ack as usize).min(self.local_inputs.len());
+        // Inputs we already have are skipped, and anything after a
+        // gap is dropped, it'll be sent again until we confirm it
+        for (tick, input) in (first as usize..).zip(inputs) {
+          if tick == self.remote_inputs.len() {
+            self.remote_inputs.push(input);
+          }
+        }
+      },
+      Packet::Bye => self.status = Status::Disconnected,
+    }
+  }
+
+  fn send(&self, packet: &Packet) {
+    if let Some(peer) = self.peer {
+      let socket = match &self.ipv4 {
+        Some(ipv4) if peer.is_ipv4() => ipv4,
+        _ => &self.socket,
+      };
+      // A packet that fails to send is no different to one lost on the way
+      let _ = socket.send_to(&packet.encode(), peer);
+    }
+  }
+}
+
+impl Drop for Session {
+  fn drop(&mut self) {
+    if self.status == Status::Connected {
+      // One last try at the inputs the other player is missing, so they
+      // can still finish a game we've already seen the end of
+      self.send(&self.inputs_packet());
+      self.send(&Packet::Bye);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::settings::Settings;
+  use crate::simulation::{Simulation, Vec2, TIMESTEP};
+  use std::thread;
+
+  fn connect() -> (Session, Session) {
+    let host = Session::host(0, 42, b"winning_score = 3".to_vec()).unwrap();
+    let port = host.local_addr().unwrap().port();
+    let client = Session::join(&format!("127.0.0.1:{}", port)).unwrap();
+    (host, client)
+  }
+
+  /// Polls both sessions until `done` is true, failing if it takes too long
+  fn pump(host: &mut Session, client: &mut Session, mut done: impl FnMut(&mut Session, &mut Session) -> bool) {
+    for _ in 0..2000 {
+      client.poll();
+      host.poll();
+      if done(host, client) {
+        return;
+      }
+      thread::sleep(Duration::from_millis(1));
+    }
+    panic!("timed out");
+  }
+
+  #[test]
+  fn packets_survive_the_trip() {
+    let packet = Packet::Inputs { ack: 7, first: 3, inputs: vec![-127, 0, 127] };
+    assert_eq!(Some(packet.clone()), Packet::decode(&packet.encode()));
+    assert_eq!(Some(Packet::Bye), Packet::decode(&Packet::Bye.encode()));
+    let welcome = Packet::Welcome { seed: 42, rules: b"rules".to_vec() };
+    assert_eq!(Some(welcome.clone()), Packet::decode(&welcome.encode()));
+    assert_eq!(None, Packet::decode(b"not a pong packet"));
+  }
+
+  #[test]
+  fn joining_over_ipv6() {
+    let mut host = Session::host(0, 42, Vec::new()).unwrap();
+    let port = host.local_addr().unwrap().port();
+    let mut client = Session::join(&format!("[::1]:{}", port)).unwrap();
+    pump(&mut host, &mut client, |host, client| {
+      host.status() == Status::Connected && client.status() == Status::Connected
+    });
+  }
+
+  #[test]
+  fn joining_player_gets_the_rules() {
+    let (mut host, mut client) = connect();
+    pump(&mut host, &mut client, |_, client| client.status() == Status::Connected);
+    assert_eq!(42, client.seed());
+    assert_eq!(b"winning_score = 3", client.rules());
+  }
+
+  #[test]
+  fn both_sides_play_the_same_game() {
+    let (mut host, mut client) = connect();
+    pump(&mut host, &mut client, |host, client| {
+      host.status() == Status::Connected && client.status() == Status::Connected
+    });
+
+    let size = Vec2::new(24.0, 104.0);
+    let mut host_game = Simulation::new(Settings::default(), 1, size, size, Vec2::new(22.0, 22.0));
+    let mut client_game = host_game.clone();
+    let mut host_ticks = 0;
+    let mut client_ticks = 0;
+
+    // Each side stops at the same tick, which may not happen at the same time
+    pump(&mut host, &mut client, |host, client| {
+      host.add_local_input(if host_ticks % 40 < 20 { 1.0 } else { -1.0 });
+      client.add_local_input(if client_ticks % 30 < 15 { -0.5 } else { 1.0 });
+      if host_ticks < 120 {
+        if let Some(inputs) = host.next_inputs() {
+          host_game.step(inputs, TIMESTEP);
+          host_ticks += 1;
+        }
+      }
+      if client_ticks < 120 {
+        if let Some(inputs) = client.next_inputs() {
+          client_game.step(inputs, TIMESTEP);
+          client_ticks += 1;
+        }
+      }
+      host_ticks == 120 && client_ticks == 120
+    });
+
+    assert_eq!(host_game, client_game);
+  }
+
+  #[test]
+  fn leaving_disconnects_the_other_player() {
+    let (mut host, mut client) = connect();
+    pump(&mut host, &mut client, |host, client| {
+      host.status() == Status::Connected && client.status() == Status::Connected
+    });
+
+    drop(client);
+    for _ in 0..1000 {
+      host.poll();
+      if host.status() == Status::Disconnected {
+        return;
+      }
+      thread::sleep(Duration::from_millis(1));
+    }
+    panic!("host never noticed the client leaving");
+  }
+
+  /// Plays both sides until the host has simulated `ticks`. Everything the host
+  /// sends near the end gets lost, so the client is left a few ticks behind.
+  fn host_finishes(host: &mut Session, client: &mut Session, ticks: usize) -> usize {
+    pump(host, client, |host, client| {
+      host.status() == Status::Connected && client.status() == Status::Connected
+    });
+    let mut client_ticks = 0;
+    let mut buffer = [0; 2048];
+    pump(host, client, |host, client| {
+      host.add_local_input(1.0);
+      client.add_local_input(-1.0);
+      if host.tick < ticks {
+        host.next_inputs();
+      }
+      if client.next_inputs().is_some() {
+        client_ticks += 1;
+      }
+      // The host's inputs go out INPUT_DELAY ticks early, so only losing the last
+      // few packets leaves the client short of inputs for the final ticks
+      if host.tick + INPUT_DELAY >= ticks {
+        thread::sleep(Duration::from_millis(1));
+        while client.socket.recv_from(&mut buffer).is_ok() {}
+      }
+      host.tick == ticks
+    });
+    assert!(client_ticks < ticks);
+    client_ticks
+  }
+
+  #[test]
+  fn inputs_keep_coming_after_the_game_ends() {
+    let (mut host, mut client) = connect();
+    let mut client_ticks = host_finishes(&mut host, &mut client, 20);
+
+    // The host only polls now, like it does on the game over screen
+    pump(&mut host, &mut client, |_, client| {
+      while client.next_inputs().is_some() {
+        client_ticks += 1;
+      }
+      client_ticks >= 20
+    });
+  }
+
+  #[test]
+  fn leaving_sends_the_last_inputs() {
+    let (mut host, mut client) = connect();
+    let mut client_ticks = host_finishes(&mut host, &mut client, 20);
+
+    drop(host);
+    for _ in 0..1000 {
+      client.poll();
+      if client.status() == Status::Disconnected {
+        break;
+      }
+      thread::sleep(Duration::from_millis(1));
+    }
+    while client.next_inputs().is_some() {
+      client_ticks += 1;
+    }
+    assert!(client_ticks >= 20, "client only got to tick {}", client_ticks);
+  }
+}
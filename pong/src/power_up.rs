@@ -0,0 +1,69 @@
+// Power-ups show up in the middle of the field every so often. Hitting the
+// ball through one gives it to whoever hit the ball last. Some happen
+// straight away, the rest are effects that last a while.
+
+use crate::settings::Settings;
+use crate::simulation::Player;
+
+// Seconds between power-ups showing up, and how many can be out at once
+pub const SPAWN_INTERVAL: f32 = 8.0;
+pub const MAX_ON_FIELD: usize = 2;
+// Power-ups are drawn as circles this wide
+pub const SIZE: f32 = 24.0;
+// How long the effects that aren't instant last, in seconds
+pub const EFFECT_DURATION: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerUp {
+  // The collector's paddle grows
+  BigPaddle,
+  // Everyone else's paddle shrinks
+  SmallPaddle,
+  // Two more balls split off the one that collected it, until the next point
+  MultiBall,
+  // The ball that collected it gets faster
+  SpeedUp,
+  // The collector's shots bend in the air
+  Curve,
+}
+
+pub const ALL: [PowerUp; 5] = [
+  PowerUp::BigPaddle,
+  PowerUp::SmallPaddle,
+  PowerUp::MultiBall,
+  PowerUp::SpeedUp,
+  PowerUp::Curve,
+];
+
+impl PowerUp {
+  pub fn name(&self) -> &'static str {
+    match self {
+      PowerUp::BigPaddle => "Big Paddle",
+      PowerUp::SmallPaddle => "Small Paddle",
+      PowerUp::MultiBall => "Multi-Ball",
+      PowerUp::SpeedUp => "Speed Up",
+      PowerUp::Curve => "Curve Shots",
+    }
+  }
+
+  /// Whether this power-up can show up in a game played with `settings`
+  pub fn enabled(&self, settings: &Settings) -> bool {
+    settings.power_ups
+      && match self {
+        PowerUp::BigPaddle => settings.big_paddle,
+        PowerUp::SmallPaddle => settings.small_paddle,
+        PowerUp::MultiBall => settings.multi_ball,
+        PowerUp::SpeedUp => settings.speed_up,
+        PowerUp::Curve => settings.curve_shots,
+      }
+  }
+}
+
+/// A power-up that's still working on `player`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Effect {
+  pub power_up: PowerUp,
+  pub player: Player,
+  // Seconds until it wears off
+  pub remaining: f32,
+}
@@ -0,0 +1,208 @@
+// Recording games so they can be watched (and debugged) later.
+//
+// The simulation is deterministic, so a replay only needs to know how the
+// game started (seed, settings and sizes) and what both players did every
+// tick. Players mostly hold a key down (or don't) for a while, so the
+// inputs are stored as runs of the same input rather than one per tick.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::settings::{self, Settings};
+use crate::simulation::{Inputs, Simulation, Vec2, MAX_PADDLES};
+
+const MAGIC: &[u8; 4] = b"PRPL";
+// Bumped whenever a replay would play out differently, so old ones are refused
+// instead of showing a game nobody played
+const VERSION: u8 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+  pub seed: u64,
+  pub settings: Settings,
+  // Player 1's paddle, player 2's paddle and the ball
+  pub sizes: [Vec2; 3],
+  // Every paddle's input for every tick, as passed to `Inputs::dequantize`
+  pub inputs: Vec<[i8; MAX_PADDLES]>,
+}
+
+impl Replay {
+  /// Starts recording a game that's about to be played from `simulation`
+  pub fn record(simulation: &Simulation) -> Replay {
+    Replay {
+      seed: simulation.seed,
+      settings: simulation.settings,
+      sizes: simulation.sizes(),
+      inputs: Vec::new(),
+    }
+  }
+
+  pub fn push(&mut self, inputs: [i8; MAX_PADDLES]) {
+    self.inputs.push(inputs);
+  }
+
+  /// How many ticks were recorded
+  pub fn len(&self) -> usize {
+    self.inputs.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.inputs.is_empty()
+  }
+
+  /// The game as it was before the first tick
+  pub fn simulation(&self) -> Simulation {
+    let [player1, player2, ball] = self.sizes;
+    Simulation::new(self.settings, self.seed, player1, player2, ball)
+  }
+
+  pub fn inputs(&self, tick: usize) -> Option<Inputs> {
+    self.inputs.get(tick).map(|&inputs| Inputs::dequantize(inputs))
+  }
+
+  pub fn load(path: &Path) -> Result<Replay, Box<dyn Error>> {
+    Replay::decode(&fs::read(path)?)
+  }
+
+  pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+      fs::create_dir_all(dir)?;
+    }
+    fs::write(path, self.encode()?)?;
+    Ok(())
+  }
+
+  /// The file format is:
+  /// "PRPL", version, seed, settings (length then TOML), sizes,
+  /// then (count, then each paddle's input) for each run of inputs
+  pub fn encode(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+    let settings = self.settings.to_toml()?;
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    bytes.extend_from_slice(&self.seed.to_le_bytes());
+    bytes.extend_from_slice(&(settings.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(settings.as_bytes());
+    for size in self.sizes {
+      bytes.extend_from_slice(&size.x.to_le_bytes());
+      bytes.extend_from_slice(&size.y.to_le_bytes());
+    }
+
+    // A run stops at 255 ticks so the count fits in a byte
+    for run in self.inputs.chunk_by(|a, b| a == b) {
+      for chunk in run.chunks(u8::MAX as usize) {
+        bytes.push(chunk.len() as u8);
+        bytes.extend(chunk[0].map(|input| input as u8));
+      }
+    }
+    Ok(bytes)
+  }
+
+  pub fn decode(bytes: &[u8]) -> Result<Replay, Box<dyn Error>> {
+    let mut bytes = bytes;
+    if take(&mut bytes, 4)? != MAGIC {
+      return Err("not a replay file".into());
+    }
+    match take(&mut bytes, 1)?[0] {
+      VERSION => {},
+      _ => return Err("replay was recorded by a different version of the game".into()),
+    }
+
+    let seed = u64::from_le_bytes(take(&mut bytes, 8)?.try_into()?);
+    let settings_len = u32::from_le_bytes(take(&mut bytes, 4)?.try_into()?);
+    let settings = Settings::from_toml(std::str::from_utf8(take(&mut bytes, settings_len as usize)?)?)?;
+
+    let mut sizes = [Vec2::zero(); 3];
+    for size in sizes.iter_mut() {
+      let x = f32::from_le_bytes(take(&mut bytes, 4)?.try_into()?);
+      let y = f32::from_le_bytes(take(&mut bytes, 4)?.try_into()?);
+      *size = Vec2::new(x, y);
+    }
+
+    let mut inputs = Vec::new();
+    while !bytes.is_empty() {
+      let run = take(&mut bytes, 1 + MAX_PADDLES)?;
+      let mut input = [0; MAX_PADDLES];
+      for (input, &byte) in input.iter_mut().zip(&run[1..]) {
+        *input = byte as i8;
+      }
+      inputs.extend(std::iter::repeat_n(input, run[0] as usize));
+    }
+
+    Ok(Replay { seed, settings, sizes, inputs })
+  }
+}
+
+/// Splits the first `count` bytes off of `bytes`
+fn take<'a>(bytes: &mut &'a [u8], count: usize) -> Result<&'a [u8], &'static str> {
+  if bytes.len() < count {
+    return Err("replay file is cut short");
+  }
+  let (taken, rest) = bytes.split_at(count);
+  *bytes = rest;
+  Ok(taken)
+}
+
+/// Where finished games are saved: `$XDG_DATA_HOME/pong/replays`,
+/// or `~/.local/share/pong/replays` when that isn't set
+pub fn replay_dir() -> Option<PathBuf> {
+  settings::user_dir("XDG_DATA_HOME", &[".local", "share"]).map(|data| data.join("pong").join("replays"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::simulation::TIMESTEP;
+
+  fn recorded_game() -> (Replay, Simulation) {
+    let size = Vec2::new(24.0, 104.0);
+    let mut sim = Simulation::new(Settings::default(), 7, size, size, Vec2::new(22.0, 22.0));
+    let mut replay = Replay::record(&sim);
+
+    for tick in 0..600 {
+      let inputs = Inputs::two(
+        if tick % 90 < 45 { 1.0 } else { -0.3 },
+        if tick % 50 < 10 { -1.0 } else { 0.0 },
+      );
+      let inputs = inputs.quantize();
+      replay.push(inputs);
+      sim.step(Inputs::dequantize(inputs), TIMESTEP);
+    }
+    (replay, sim)
+  }
+
+  #[test]
+  fn survives_saving_and_loading() {
+    let (replay, _) = recorded_game();
+    let bytes = replay.encode().unwrap();
+
+    assert_eq!(replay, Replay::decode(&bytes).unwrap());
+    // 600 ticks of inputs, but only a few runs of them.
+    // The settings are saved as text, so they grow with every new setting.
+    let inputs = bytes.len() - replay.settings.to_toml().unwrap().len();
+    assert!(inputs < 600, "inputs took {} bytes", inputs);
+  }
+
+  #[test]
+  fn plays_back_exactly() {
+    let (replay, live) = recorded_game();
+
+    let mut sim = replay.simulation();
+    for tick in 0..replay.len() {
+      sim.step(replay.inputs(tick).unwrap(), TIMESTEP);
+    }
+    assert_eq!(live, sim);
+  }
+
+  #[test]
+  fn rejects_other_files() {
+    assert!(Replay::decode(b"not a replay").is_err());
+    let (replay, _) = recorded_game();
+    let bytes = replay.encode().unwrap();
+    assert!(Replay::decode(&bytes[..bytes.len() - 1]).is_err());
+    let mut old = bytes.clone();
+    old[4] = 2;
+    assert!(Replay::decode(&old).is_err());
+  }
+}
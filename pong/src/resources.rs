@@ -0,0 +1,95 @@
+// Everything the game loads from `resources/`, loaded once when it starts.
+// Reloading a font means reading and rasterizing the whole file again, so
+// anything drawn every frame should come from here.
+
+use std::path::{Path, PathBuf};
+
+use tetra::audio::Sound;
+use tetra::graphics::mesh::{Mesh, ShapeStyle};
+use tetra::graphics::text::{Font, Text};
+use tetra::graphics::{Rectangle, Shader, Texture};
+use tetra::math::Vec2;
+use tetra::Context;
+
+use crate::power_up;
+use crate::visuals;
+
+/// Where the resources are. Next to the executable when the game has been
+/// installed somewhere, otherwise in the project itself, so the game can be
+/// started from any directory and not just from the project with `cargo run`.
+pub fn resource_dir() -> PathBuf {
+  let next_to_exe = std::env::current_exe()
+    .ok()
+    .and_then(|exe| exe.parent().map(|dir| dir.join("resources")))
+    .filter(|dir| dir.is_dir());
+  next_to_exe.unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("resources"))
+}
+
+pub struct Resources {
+  pub player1: Texture,
+  pub player2: Texture,
+  pub ball: Texture,
+  // Power-ups are just circles, drawn in a different color for each kind
+  pub power_up: Mesh,
+  pub spark: Mesh,
+  // Run over the whole screen when post-processing is turned on
+  pub post_process: Shader,
+  // Scores and messages
+  pub large_font: Font,
+  // Menus and the clock
+  pub small_font: Font,
+  pub sounds: Sounds,
+}
+
+/// Sounds are optional, the game is still playable without them
+pub struct Sounds {
+  pub paddle: Option<Sound>,
+  pub wall: Option<Sound>,
+  pub score: Option<Sound>,
+  pub win: Option<Sound>,
+  pub music: Option<Sound>,
+}
+
+impl Resources {
+  pub fn load(ctx: &mut Context) -> tetra::Result<Resources> {
+    let dir = resource_dir();
+    let radius = power_up::SIZE / 2.0;
+    let spark = Rectangle::new(0.0, 0.0, visuals::SPARK_SIZE, visuals::SPARK_SIZE);
+
+    // Texture is a type that represents image data that has been loaded
+    // into graphics memory.
+    Ok(Resources {
+      player1: Texture::new(ctx, dir.join("player1.png"))?,
+      player2: Texture::new(ctx, dir.join("player2.png"))?,
+      ball: Texture::new(ctx, dir.join("ball.png"))?,
+      power_up: Mesh::circle(ctx, ShapeStyle::Fill, Vec2::broadcast(radius), radius)?,
+      spark: Mesh::rectangle(ctx, ShapeStyle::Fill, spark)?,
+      post_process: Shader::from_fragment_file(ctx, dir.join("post_process.frag"))?,
+      large_font: Font::vector(ctx, dir.join("Arial.ttf"), 32.0)?,
+      small_font: Font::vector(ctx, dir.join("Arial.ttf"), 24.0)?,
+      sounds: Sounds {
+        paddle: load_sound(&dir.join("paddle.wav")),
+        wall: load_sound(&dir.join("wall.wav")),
+        score: load_sound(&dir.join("score.wav")),
+        win: load_sound(&dir.join("win.wav")),
+        music: load_sound(&dir.join("music.wav")),
+      },
+    })
+  }
+}
+
+fn load_sound(path: &Path) -> Option<Sound> {
+  match Sound::new(path) {
+    Ok(sound) => Some(sound),
+    Err(err) => {
+      eprintln!("Couldn't load {}: {}", path.display(), err);
+      None
+    },
+  }
+}
+
+/// Draws `text` so its middle is at `x`, with its top at `y`
+pub fn draw_centered(ctx: &mut Context, text: &mut Text, x: f32, y: f32) {
+  let width = text.get_bounds(ctx).map(|bounds| bounds.width).unwrap_or_default();
+  text.draw(ctx, Vec2::new(x - width / 2.0, y));
+}
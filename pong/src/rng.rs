@@ -0,0 +1,42 @@
+// A tiny random number generator (xorshift64*). It's nowhere near good
+// enough for anything important, but it's plenty for a game, and unlike
+// pulling in the `rand` crate, the same seed gives the same numbers everywhere.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rng {
+  state: u64,
+}
+
+impl Rng {
+  pub fn new(seed: u64) -> Rng {
+    // xorshift gets stuck on 0 forever
+    Rng { state: if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed } }
+  }
+
+  /// Seeds from the clock, for when it doesn't matter which numbers come out
+  pub fn from_time() -> Rng {
+    let nanos = std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .map(|duration| duration.as_nanos() as u64)
+      .unwrap_or(0);
+    Rng::new(nanos)
+  }
+
+  pub fn next_u64(&mut self) -> u64 {
+    self.state ^= self.state >> 12;
+    self.state ^= self.state << 25;
+    self.state ^= self.state >> 27;
+    self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+  }
+
+  /// A number between 0.0 (inclusive) and 1.0 (exclusive)
+  pub fn next_f32(&mut self) -> f32 {
+    // The top 24 bits fit exactly in an f32's mantissa
+    (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+  }
+
+  /// A number between `min` and `max`
+  pub fn range(&mut self, min: f32, max: f32) -> f32 {
+    min + (max - min) * self.next_f32()
+  }
+}
@@ -0,0 +1,453 @@
+// Everything about the game that can be changed without recompiling.
+// Settings are loaded from a TOML file, can be overridden on the command
+// line and edited from the options menu, which saves them back to the file
+// (the command line overrides are left out, they only last for that run).
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// How many paddles there are and where
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+  // One paddle on the left and one on the right
+  Classic,
+  // Adds a third player guarding the top, the bottom is still a wall
+  ThreeWay,
+  // A player on every side
+  FourWay,
+  // Two teams of two, one paddle at the back of each side and one further forward
+  Doubles,
+}
+
+pub const MODES: [Mode; 4] = [Mode::Classic, Mode::ThreeWay, Mode::FourWay, Mode::Doubles];
+
+impl Mode {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Mode::Classic => "Classic",
+      Mode::ThreeWay => "Three Way",
+      Mode::FourWay => "Four Way",
+      Mode::Doubles => "Doubles",
+    }
+  }
+
+  /// How many players (or teams, in doubles) have a goal to guard
+  pub fn players(&self) -> usize {
+    match self {
+      Mode::Classic | Mode::Doubles => 2,
+      Mode::ThreeWay => 3,
+      Mode::FourWay => 4,
+    }
+  }
+
+  pub fn paddles(&self) -> usize {
+    match self {
+      Mode::Classic => 2,
+      Mode::ThreeWay => 3,
+      Mode::FourWay | Mode::Doubles => 4,
+    }
+  }
+
+  /// With more than two sides, everyone starts with the winning score as lives
+  /// and is knocked out when they run out, instead of playing up to a score
+  pub fn elimination(&self) -> bool {
+    self.players() > 2
+  }
+
+  fn parse(name: &str) -> Option<Mode> {
+    match name {
+      "classic" => Some(Mode::Classic),
+      "three-way" => Some(Mode::ThreeWay),
+      "four-way" => Some(Mode::FourWay),
+      "doubles" => Some(Mode::Doubles),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+// Anything missing from the file keeps its default
+#[serde(default)]
+pub struct Settings {
+  // It will be easier to use these values as floats and cast to i32 when
+  // we need to. This is the size of the playing field, everything happens
+  // in these coordinates. The window starts out this size, and when it's
+  // resized (or fullscreen) the field is scaled up or down to fit.
+  pub window_width: f32,
+  pub window_height: f32,
+  pub fullscreen: bool,
+
+  // Ball and Paddle Constraints, in pixels per second
+  pub paddle_speed: f32,
+  pub ball_speed: f32,
+  // How much faster the ball gets with every paddle hit, and the fastest it can go
+  pub ball_acc: f32,
+  pub max_ball_speed: f32,
+  // In degrees, how far off straight the ball goes when it hits the end of a paddle
+  pub max_bounce_angle: f32,
+  // How much of a moving paddle's speed the ball picks up sideways, from 0.0 to 1.0
+  pub spin_transfer: f32,
+
+  // Game Paramters
+  pub mode: Mode,
+  // Lives instead, in the modes with more than two sides
+  pub winning_score: i32,
+  // Keep playing past the winning score until someone is two points ahead
+  pub win_by_two: bool,
+  // In seconds, 0 means no limit. When time's up whoever is ahead wins,
+  // and if it's a draw the next point wins.
+  pub time_limit: u32,
+
+  // Power-ups show up on the field when this is on.
+  // Each kind can also be turned off on its own.
+  pub power_ups: bool,
+  pub big_paddle: bool,
+  pub small_paddle: bool,
+  pub multi_ball: bool,
+  pub speed_up: bool,
+  pub curve_shots: bool,
+
+  // Sound, from 0.0 (silent) to 1.0
+  pub volume: f32,
+  pub muted: bool,
+
+  // Sparks, ball trails and screen shake
+  pub effects: bool,
+  // An old TV look for the whole screen
+  pub post_processing: bool,
+}
+
+impl Default for Settings {
+  fn default() -> Settings {
+    Settings {
+      window_width: 640.0,
+      window_height: 480.0,
+      fullscreen: false,
+      paddle_speed: 960.0,
+      ball_speed: 300.0,
+      ball_acc: 3.0,
+      max_ball_speed: 1200.0,
+      max_bounce_angle: 60.0,
+      spin_transfer: 0.3,
+      mode: Mode::Classic,
+      winning_score: 5,
+      win_by_two: false,
+      time_limit: 0,
+      power_ups: false,
+      big_paddle: true,
+      small_paddle: true,
+      multi_ball: true,
+      speed_up: true,
+      curve_shots: true,
+      volume: 0.7,
+      muted: false,
+      effects: true,
+      post_processing: false,
+    }
+  }
+}
+
+/// What can be changed in the options menu.
+/// The field size isn't in the menu, changing it needs a restart,
+/// and neither are the separate power-ups or the limits on the ball, to keep the menu short.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuOption {
+  Mode,
+  WinningScore,
+  WinByTwo,
+  TimeLimit,
+  PowerUps,
+  BallSpeed,
+  BallAcc,
+  PaddleSpeed,
+  SpinTransfer,
+  Volume,
+  Effects,
+  PostProcessing,
+}
+
+impl MenuOption {
+  /// In the order they show up in the menu
+  pub const ALL: [MenuOption; 12] = [
+    MenuOption::Mode,
+    MenuOption::WinningScore,
+    MenuOption::WinByTwo,
+    MenuOption::TimeLimit,
+    MenuOption::PowerUps,
+    MenuOption::BallSpeed,
+    MenuOption::BallAcc,
+    MenuOption::PaddleSpeed,
+    MenuOption::SpinTransfer,
+    MenuOption::Volume,
+    MenuOption::Effects,
+    MenuOption::PostProcessing,
+  ];
+}
+
+/// The directory in the XDG variable `var`, or `fallback` under the home
+/// directory when it isn't set (the spec says to ignore relative paths too)
+pub fn user_dir(var: &str, fallback: &[&str]) -> Option<PathBuf> {
+  std::env::var_os(var)
+    .map(PathBuf::from)
+    .filter(|dir| dir.is_absolute())
+    .or_else(|| std::env::var_os("HOME").map(|home| fallback.iter().fold(PathBuf::from(home), |dir, part| dir.join(part))))
+}
+
+impl Settings {
+  /// Where the settings file lives: `$XDG_CONFIG_HOME/pong/settings.toml`,
+  /// or `~/.config/pong/settings.toml` when that isn't set
+  pub fn path() -> Option<PathBuf> {
+    user_dir("XDG_CONFIG_HOME", &[".config"]).map(|config| config.join("pong").join("settings.toml"))
+  }
+
+  /// Reads the settings file. A missing file just means the defaults.
+  pub fn load(path: &Path) -> Result<Settings, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+      Ok(contents) => Settings::from_toml(&contents),
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Settings::default()),
+      Err(err) => Err(err.into()),
+    }
+  }
+
+  pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+      fs::create_dir_all(dir)?;
+    }
+    fs::write(path, self.to_toml()?)?;
+    Ok(())
+  }
+
+  pub fn from_toml(contents: &str) -> Result<Settings, Box<dyn Error>> {
+    let settings: Settings = toml::from_str(contents)?;
+    settings.check()?;
+    Ok(settings)
+  }
+
+  pub fn to_toml(self) -> Result<String, Box<dyn Error>> {
+    Ok(toml::to_string(&self)?)
+  }
+
+  /// Changes one setting by name, for command line overrides like `--ball-speed 400`
+  /// Nothing changes if the new value isn't allowed.
+  pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+    let invalid = || format!("invalid value for --{}: {}", name, value);
+    let mut changed = *self;
+    match name {
+      "window-width" => changed.window_width = value.parse().map_err(|_| invalid())?,
+      "window-height" => changed.window_height = value.parse().map_err(|_| invalid())?,
+      "fullscreen" => changed.fullscreen = value.parse().map_err(|_| invalid())?,
+      "paddle-speed" => changed.paddle_speed = value.parse().map_err(|_| invalid())?,
+      "ball-speed" => changed.ball_speed = value.parse().map_err(|_| invalid())?,
+      "ball-acc" => changed.ball_acc = value.parse().map_err(|_| invalid())?,
+      "max-ball-speed" => changed.max_ball_speed = value.parse().map_err(|_| invalid())?,
+      "max-bounce-angle" => changed.max_bounce_angle = value.parse().map_err(|_| invalid())?,
+      "spin-transfer" => changed.spin_transfer = value.parse().map_err(|_| invalid())?,
+      "mode" => changed.mode = Mode::parse(value).ok_or_else(invalid)?,
+      "winning-score" => changed.winning_score = value.parse().map_err(|_| invalid())?,
+      "win-by-two" => changed.win_by_two = value.parse().map_err(|_| invalid())?,
+      "time-limit" => changed.time_limit = value.parse().map_err(|_| invalid())?,
+      "power-ups" => changed.power_ups = value.parse().map_err(|_| invalid())?,
+      "big-paddle" => changed.big_paddle = value.parse().map_err(|_| invalid())?,
+      "small-paddle" => changed.small_paddle = value.parse().map_err(|_| invalid())?,
+      "multi-ball" => changed.multi_ball = value.parse().map_err(|_| invalid())?,
+      "speed-up" => changed.speed_up = value.parse().map_err(|_| invalid())?,
+      "curve-shots" => changed.curve_shots = value.parse().map_err(|_| invalid())?,
+      "volume" => changed.volume = value.parse().map_err(|_| invalid())?,
+      "muted" => changed.muted = value.parse().map_err(|_| invalid())?,
+      "effects" => changed.effects = value.parse().map_err(|_| invalid())?,
+      "post-processing" => changed.post_processing = value.parse().map_err(|_| invalid())?,
+      _ => return Err(format!("unknown setting: --{}", name)),
+    }
+    changed.check()?;
+    *self = changed;
+    Ok(())
+  }
+
+  /// Catches settings the game can't be played with
+  fn check(&self) -> Result<(), &'static str> {
+    let numbers = [
+      self.window_width,
+      self.window_height,
+      self.paddle_speed,
+      self.ball_speed,
+      self.ball_acc,
+      self.max_ball_speed,
+      self.max_bounce_angle,
+      self.spin_transfer,
+      self.volume,
+    ];
+    // NaN gets past every comparison below, so it has to be caught first
+    if numbers.iter().any(|number| !number.is_finite()) {
+      return Err("settings can't be NaN or infinite");
+    }
+    if self.window_width < 200.0 || self.window_height < 200.0 {
+      return Err("the window has to be at least 200x200");
+    }
+    if self.winning_score < 1 {
+      return Err("the winning score has to be at least 1");
+    }
+    if self.paddle_speed <= 0.0 || self.ball_speed <= 0.0 {
+      return Err("the paddles and ball have to be able to move");
+    }
+    if self.ball_acc < 0.0 {
+      return Err("the ball can't slow down when it's hit");
+    }
+    if self.max_ball_speed < self.ball_speed {
+      return Err("the ball's top speed can't be slower than it's served");
+    }
+    // Any steeper and the ball could go back and forth along the paddle forever
+    if !(0.0..=80.0).contains(&self.max_bounce_angle) {
+      return Err("the bounce angle has to be between 0 and 80 degrees");
+    }
+    if !(0.0..=1.0).contains(&self.spin_transfer) {
+      return Err("the spin transfer has to be between 0 and 1");
+    }
+    if !(0.0..=1.0).contains(&self.volume) {
+      return Err("the volume has to be between 0 and 1");
+    }
+    Ok(())
+  }
+
+  /// Turns the volume up (or down, when `change` is negative).
+  /// Rounded to whole percents so repeated changes don't drift.
+  pub fn change_volume(&mut self, change: f32) {
+    self.volume = ((self.volume + change).clamp(0.0, 1.0) * 100.0).round() / 100.0;
+  }
+
+  /// What the options menu shows for `option`
+  pub fn menu_label(&self, option: MenuOption) -> String {
+    match option {
+      MenuOption::Mode => format!("Mode: {}", self.mode.name()),
+      MenuOption::WinningScore if self.mode.elimination() => format!("Lives: {}", self.winning_score),
+      MenuOption::WinningScore => format!("Winning Score: {}", self.winning_score),
+      MenuOption::WinByTwo => format!("Win By Two: {}", if self.win_by_two { "On" } else { "Off" }),
+      MenuOption::TimeLimit if self.time_limit == 0 => String::from("Time Limit: None"),
+      MenuOption::TimeLimit => format!("Time Limit: {}:{:02}", self.time_limit / 60, self.time_limit % 60),
+      MenuOption::PowerUps => format!("Power-Ups: {}", if self.power_ups { "On" } else { "Off" }),
+      MenuOption::BallSpeed => format!("Ball Speed: {}", self.ball_speed),
+      MenuOption::BallAcc => format!("Ball Acceleration: {}", self.ball_acc),
+      MenuOption::PaddleSpeed => format!("Paddle Speed: {}", self.paddle_speed),
+      MenuOption::SpinTransfer => format!("Spin Transfer: {}%", (self.spin_transfer * 100.0).round()),
+      MenuOption::Volume => format!("Volume: {}%", (self.volume * 100.0).round()),
+      MenuOption::Effects => format!("Effects: {}", if self.effects { "On" } else { "Off" }),
+      MenuOption::PostProcessing => format!("Post-Processing: {}", if self.post_processing { "On" } else { "Off" }),
+    }
+  }
+
+  /// Copies `option` over from `other`. Menu changes are made to the
+  /// settings being played with, and copied to the ones that get saved this way,
+  /// so command line overrides don't end up in the file.
+  pub fn menu_copy(&mut self, option: MenuOption, other: &Settings) {
+    match option {
+      MenuOption::Mode => self.mode = other.mode,
+      MenuOption::WinningScore => self.winning_score = other.winning_score,
+      MenuOption::WinByTwo => self.win_by_two = other.win_by_two,
+      MenuOption::TimeLimit => self.time_limit = other.time_limit,
+      MenuOption::PowerUps => self.power_ups = other.power_ups,
+      // The top speed might only have been raised on the command line
+      MenuOption::BallSpeed => self.ball_speed = other.ball_speed.min(self.max_ball_speed),
+      MenuOption::BallAcc => self.ball_acc = other.ball_acc,
+      MenuOption::PaddleSpeed => self.paddle_speed = other.paddle_speed,
+      MenuOption::SpinTransfer => self.spin_transfer = other.spin_transfer,
+      MenuOption::Volume => self.volume = other.volume,
+      MenuOption::Effects => self.effects = other.effects,
+      MenuOption::PostProcessing => self.post_processing = other.post_processing,
+    }
+  }
+
+  /// Nudges `option` up (`steps` > 0) or down, keeping it sensible
+  pub fn menu_adjust(&mut self, option: MenuOption, steps: i32) {
+    let nudge = |value: f32, step: f32, min: f32, max: f32| (value + step * steps as f32).clamp(min, max);
+    match option {
+      MenuOption::Mode => {
+        let index = MODES.iter().position(|&mode| mode == self.mode).unwrap_or(0) as i32;
+        self.mode = MODES[(index + steps).rem_euclid(MODES.len() as i32) as usize];
+      },
+      MenuOption::WinningScore => self.winning_score = (self.winning_score + steps).clamp(1, 21),
+      MenuOption::WinByTwo => self.win_by_two = !self.win_by_two,
+      MenuOption::TimeLimit => self.time_limit = (self.time_limit as i32 + 30 * steps).clamp(0, 600) as u32,
+      MenuOption::PowerUps => self.power_ups = !self.power_ups,
+      MenuOption::BallSpeed => self.ball_speed = nudge(self.ball_speed, 50.0, 100.0, 1000.0).min(self.max_ball_speed),
+      MenuOption::BallAcc => self.ball_acc = nudge(self.ball_acc, 1.0, 0.0, 20.0),
+      MenuOption::PaddleSpeed => self.paddle_speed = nudge(self.paddle_speed, 60.0, 120.0, 1920.0),
+      // Rounded like the volume, so repeated nudges don't drift
+      MenuOption::SpinTransfer => self.spin_transfer = (nudge(self.spin_transfer, 0.1, 0.0, 1.0) * 10.0).round() / 10.0,
+      MenuOption::Volume => self.change_volume(0.1 * steps as f32),
+      MenuOption::Effects => self.effects = !self.effects,
+      MenuOption::PostProcessing => self.post_processing = !self.post_processing,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn missing_fields_keep_their_defaults() {
+    let settings = Settings::from_toml("winning_score = 11\nwin_by_two = true").unwrap();
+    assert_eq!(Settings { winning_score: 11, win_by_two: true, ..Settings::default() }, settings);
+  }
+
+  #[test]
+  fn survives_saving_and_loading() {
+    let settings = Settings { time_limit: 90, ball_speed: 450.0, ..Settings::default() };
+    assert_eq!(settings, Settings::from_toml(&settings.to_toml().unwrap()).unwrap());
+  }
+
+  #[test]
+  fn command_line_overrides() {
+    let mut settings = Settings::default();
+    settings.set("ball-speed", "400").unwrap();
+    settings.set("win-by-two", "true").unwrap();
+    settings.set("mode", "four-way").unwrap();
+    settings.set("fullscreen", "true").unwrap();
+
+    assert_eq!(400.0, settings.ball_speed);
+    assert!(settings.win_by_two);
+    assert_eq!(Mode::FourWay, settings.mode);
+    assert!(settings.fullscreen);
+    assert!(settings.set("ball-speed", "fast").is_err());
+    assert!(settings.set("winning-score", "0").is_err());
+    assert!(settings.set("gravity", "1").is_err());
+    assert!(settings.set("max-bounce-angle", "90").is_err());
+    assert!(settings.set("max-ball-speed", "100").is_err());
+  }
+
+  #[test]
+  fn rejected_overrides_change_nothing() {
+    let mut settings = Settings::default();
+    for (name, value) in [("ball-speed", "NaN"), ("paddle-speed", "inf"), ("ball-acc", "-1"), ("window-width", "NaN")] {
+      assert!(settings.set(name, value).is_err(), "--{} {} was allowed", name, value);
+    }
+    assert!(settings.set("max-ball-speed", "100").is_err());
+    assert_eq!(Settings::default(), settings);
+    assert!(Settings::from_toml("ball_speed = nan").is_err());
+  }
+
+  #[test]
+  fn menu_changes_leave_overrides_out() {
+    let saved = Settings::default();
+    let mut playing = saved;
+    playing.set("paddle-speed", "1200").unwrap();
+    playing.set("winning-score", "11").unwrap();
+
+    let mut edited = saved;
+    playing.menu_adjust(MenuOption::TimeLimit, 2);
+    edited.menu_copy(MenuOption::TimeLimit, &playing);
+    assert_eq!(Settings { time_limit: 60, ..saved }, edited);
+  }
+
+  #[test]
+  fn menu_keeps_values_in_range() {
+    let mut settings = Settings::default();
+    settings.menu_adjust(MenuOption::TimeLimit, -1);
+    assert_eq!(0, settings.time_limit);
+    settings.menu_adjust(MenuOption::TimeLimit, 3);
+    assert_eq!("Time Limit: 1:30", settings.menu_label(MenuOption::TimeLimit));
+  }
+}
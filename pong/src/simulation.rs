@@ -0,0 +1,1306 @@
+// The game logic lives here, away from tetra, so that it can run
+// (and be tested) without a window. `GameState` in main.rs wraps a
+// `Simulation`, feeds it the keyboard state every tick and draws the result.
+
+use std::ops::{Add, AddAssign, Mul, Sub};
+
+use crate::math;
+use crate::power_up::{self, Effect, PowerUp};
+use crate::rng::Rng;
+use crate::settings::{Mode, Settings};
+
+// The simulation always moves forward in steps of the same size, no
+// matter how fast the game is drawn. That keeps the physics the same
+// on every machine (and makes it deterministic).
+pub const TICKS_PER_SECOND: f64 = 60.0;
+pub const TIMESTEP: f32 = 1.0 / TICKS_PER_SECOND as f32;
+
+// Everything else that changes how the game plays is in `Settings`
+
+// Distance between the paddles and the edge of the screen
+const PADDLE_MARGIN: f32 = 16.0;
+// In doubles, how far across the field the front paddles are
+const DOUBLES_FRONT: f32 = 0.25;
+
+// The most paddles a game can have, one on each side or two teams of two
+pub const MAX_PADDLES: usize = 4;
+
+// How steep a serve can be, as sideways speed compared to forward speed
+const SERVE_ANGLE: f32 = 0.5;
+
+// What the power-ups do: how much paddles grow and shrink (and the most
+// they can when several stack up), how much faster speed up makes the
+// ball and how hard curve shots bend, in pixels per second per second
+const BIG_PADDLE: f32 = 1.5;
+const SMALL_PADDLE: f32 = 0.6;
+const MIN_PADDLE_SCALE: f32 = 0.4;
+const MAX_PADDLE_SCALE: f32 = 2.25;
+const SPEED_UP: f32 = 1.5;
+const CURVE: f32 = 250.0;
+const MAX_BALLS: usize = 4;
+
+// tetra's Vec2 would do, but the simulation shouldn't need tetra at all.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec2 {
+  pub x: f32,
+  pub y: f32,
+}
+
+impl Vec2 {
+  pub const fn new(x: f32, y: f32) -> Vec2 {
+    Vec2 { x, y }
+  }
+
+  pub const fn zero() -> Vec2 {
+    Vec2::new(0.0, 0.0)
+  }
+
+  pub fn length(&self) -> f32 {
+    math::hypot(self.x, self.y)
+  }
+
+  /// The same direction, but no longer than `max`
+  pub fn clamp_length(self, max: f32) -> Vec2 {
+    let length = self.length();
+    if length > max {
+      self * (max / length)
+    } else {
+      self
+    }
+  }
+}
+
+// The game draws with tetra's Vec2, this lets it hand the simulation's
+// positions straight to `draw`
+#[cfg(feature = "tetra")]
+impl From<Vec2> for tetra::math::Vec2<f32> {
+  fn from(v: Vec2) -> tetra::math::Vec2<f32> {
+    tetra::math::Vec2::new(v.x, v.y)
+  }
+}
+
+impl Add for Vec2 {
+  type Output = Vec2;
+
+  fn add(self, other: Vec2) -> Vec2 {
+    Vec2::new(self.x + other.x, self.y + other.y)
+  }
+}
+
+impl AddAssign for Vec2 {
+  fn add_assign(&mut self, other: Vec2) {
+    *self = *self + other;
+  }
+}
+
+impl Sub for Vec2 {
+  type Output = Vec2;
+
+  fn sub(self, other: Vec2) -> Vec2 {
+    Vec2::new(self.x - other.x, self.y - other.y)
+  }
+}
+
+impl Mul<f32> for Vec2 {
+  type Output = Vec2;
+
+  fn mul(self, scale: f32) -> Vec2 {
+    Vec2::new(self.x * scale, self.y * scale)
+  }
+}
+
+// To make the ball collide with the paddles and the walls, we will
+// implement *axis-aligned bounding boxes* (AABBs). This technique
+// takes a rectange and does some math to determine if that rectangle
+// intersects with another rectangle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+  pub x: f32,
+  pub y: f32,
+  pub width: f32,
+  pub height: f32,
+}
+
+impl Rect {
+  /// Checking whether two rectangles overlap after moving lets a fast ball
+  /// skip straight over a paddle between two steps. Instead this checks the whole path:
+  /// it returns how far along `motion` (0.0 to 1.0) this rectangle first
+  /// touches `target`, or `None` if it doesn't touch it at all.
+  pub fn sweep(&self, motion: Vec2, target: &Rect) -> Option<f32> {
+    let (x_entry, x_exit) = sweep_axis(self.x, self.width, motion.x, target.x, target.width)?;
+    let (y_entry, y_exit) = sweep_axis(self.y, self.height, motion.y, target.y, target.height)?;
+
+    // Touching means overlapping on both axes at the same time
+    let entry = x_entry.max(y_entry);
+    let exit = x_exit.min(y_exit);
+    if entry >= exit || entry > 1.0 || exit <= 0.0 {
+      return None;
+    }
+    Some(entry.max(0.0))
+  }
+
+  pub fn overlaps(&self, other: &Rect) -> bool {
+    self.x < other.x + other.width
+      && self.x + self.width > other.x
+      && self.y < other.y + other.height
+      && self.y + self.height > other.y
+  }
+}
+
+/// When (as a fraction of `motion`) a moving span starts and stops overlapping a still one
+fn sweep_axis(start: f32, size: f32, motion: f32, target: f32, target_size: f32) -> Option<(f32, f32)> {
+  if motion == 0.0 {
+    // Not moving on this axis, so it either always overlaps or never does
+    let overlapping = start < target + target_size && start + size > target;
+    return overlapping.then_some((f32::NEG_INFINITY, f32::INFINITY));
+  }
+
+  let near = if motion > 0.0 { target - (start + size) } else { target + target_size - start };
+  let far = if motion > 0.0 { target + target_size - start } else { target - (start + size) };
+  Some((near / motion, far / motion))
+}
+
+/// The edges of the field. Each one is either a player's goal or a wall.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+  Left,
+  Right,
+  Top,
+  Bottom,
+}
+
+impl Side {
+  pub const ALL: [Side; 4] = [Side::Left, Side::Right, Side::Top, Side::Bottom];
+
+  /// Paddles on the left and right move up and down, the others move sideways
+  pub fn is_vertical(&self) -> bool {
+    matches!(self, Side::Left | Side::Right)
+  }
+
+  pub fn opposite(&self) -> Side {
+    match self {
+      Side::Left => Side::Right,
+      Side::Right => Side::Left,
+      Side::Top => Side::Bottom,
+      Side::Bottom => Side::Top,
+    }
+  }
+
+  /// Whether something moving at `velocity` is heading towards this side
+  pub fn facing(&self, velocity: Vec2) -> bool {
+    match self {
+      Side::Left => velocity.x < 0.0,
+      Side::Right => velocity.x > 0.0,
+      Side::Top => velocity.y < 0.0,
+      Side::Bottom => velocity.y > 0.0,
+    }
+  }
+}
+
+/// In doubles, players one and two are the two teams
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Player {
+  One,
+  Two,
+  Three,
+  Four,
+}
+
+impl Player {
+  pub const ALL: [Player; 4] = [Player::One, Player::Two, Player::Three, Player::Four];
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Player::One => "Player 1",
+      Player::Two => "Player 2",
+      Player::Three => "Player 3",
+      Player::Four => "Player 4",
+    }
+  }
+
+  pub fn index(&self) -> usize {
+    *self as usize
+  }
+
+  pub fn from_index(index: usize) -> Player {
+    Player::ALL[index]
+  }
+
+  /// The side of the field this player guards
+  pub fn side(&self) -> Side {
+    Side::ALL[self.index()]
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntityType {
+  Paddle(Player),
+  Ball,
+  PowerUp(PowerUp),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entity {
+  pub position: Vec2,
+  pub velocity: Vec2,
+  // Taken from the size of the texture that gets drawn for it
+  pub size: Vec2,
+  pub entity_type: EntityType,
+  // Whoever hit this ball last, they get any power-up it goes through
+  pub last_hit: Option<Player>,
+  // How hard a curve shot pulls the ball sideways, in pixels per second per second
+  pub curve: Vec2,
+}
+
+impl Entity {
+  pub fn paddle(player: Player, size: Vec2, position: Vec2) -> Entity {
+    Entity::with_velocity(size, position, Vec2::zero(), EntityType::Paddle(player))
+  }
+
+  pub fn with_velocity(size: Vec2, position: Vec2, velocity: Vec2, entity_type: EntityType) -> Entity {
+    Entity {
+      position,
+      velocity,
+      size,
+      entity_type,
+      last_hit: None,
+      curve: Vec2::zero(),
+    }
+  }
+
+  pub fn width(&self) -> f32 {
+    self.size.x
+  }
+
+  pub fn height(&self) -> f32 {
+    self.size.y
+  }
+
+  pub fn bounds(&self) -> Rect {
+    Rect {
+      x: self.position.x,
+      y: self.position.y,
+      width: self.width(),
+      height: self.height(),
+    }
+  }
+
+  pub fn center(&self) -> Vec2 {
+    Vec2::new(
+      self.position.x + self.width() / 2.0,
+      self.position.y + self.height() / 2.0,
+    )
+  }
+
+  /// Who a paddle belongs to
+  pub fn player(&self) -> Option<Player> {
+    match self.entity_type {
+      EntityType::Paddle(player) => Some(player),
+      _ => None,
+    }
+  }
+
+  pub fn reset(&mut self, settings: &Settings) {
+    match self.entity_type {
+      // Paddles go back to the middle of their side
+      EntityType::Paddle(player) if player.side().is_vertical() => {
+        self.position.y = (settings.window_height - self.height()) / 2.0;
+        self.velocity = Vec2::zero();
+      },
+      EntityType::Paddle(_) => {
+        self.position.x = (settings.window_width - self.width()) / 2.0;
+        self.velocity = Vec2::zero();
+      },
+      EntityType::Ball => {
+        // used to reset the ball to the center of the screen
+        self.position.x = settings.window_width / 2.0 - self.width() / 2.0;
+        self.position.y = settings.window_height / 2.0 - self.height() / 2.0;
+        self.last_hit = None;
+        self.curve = Vec2::zero();
+      },
+      // Power-ups don't move
+      EntityType::PowerUp(_) => {},
+    }
+  }
+
+  /// Moves a paddle for `dt` seconds in the direction of `movement`
+  /// (-1.0 is full speed up or left, 1.0 full speed down or right) and keeps it on the screen
+  fn move_paddle(&mut self, movement: f32, dt: f32, settings: &Settings) {
+    let distance = settings.paddle_speed * movement.clamp(-1.0, 1.0) * dt;
+    let start = self.position;
+
+    // Make sure the paddle doesn't go off the screen.
+    if self.is_vertical() {
+      self.position.y = (self.position.y + distance).clamp(0.0, settings.window_height - self.height());
+    } else {
+      self.position.x = (self.position.x + distance).clamp(0.0, settings.window_width - self.width());
+    }
+
+    // How fast it actually went, a paddle up against the edge isn't moving.
+    // The ball picks some of this up when it's hit.
+    if dt > 0.0 {
+      self.velocity = (self.position - start) * (1.0 / dt);
+    }
+  }
+
+  /// Which way a paddle moves
+  pub fn is_vertical(&self) -> bool {
+    self.player().is_none_or(|player| player.side().is_vertical())
+  }
+
+  /// Changes how long a paddle is without moving its center
+  fn resize_paddle(&mut self, length: f32, settings: &Settings) {
+    let center = self.center();
+    if self.is_vertical() {
+      self.size.y = length.min(settings.window_height);
+      self.position.y = (center.y - self.height() / 2.0).clamp(0.0, settings.window_height - self.height());
+    } else {
+      self.size.x = length.min(settings.window_width);
+      self.position.x = (center.x - self.width() / 2.0).clamp(0.0, settings.window_width - self.width());
+    }
+  }
+
+  /// Moves a ball for `dt` seconds, bouncing it off whichever of `paddles` it reaches first
+  /// and off the `walls` (the sides without anyone guarding them, in `Side::ALL` order).
+  /// `curving` says which players have curve shots.
+  fn move_ball(
+    &mut self,
+    paddles: &[&Entity],
+    walls: [bool; 4],
+    curving: impl Fn(Player) -> bool,
+    dt: f32,
+    settings: &Settings,
+    events: &mut Vec<Event>,
+  ) {
+    // A curve can't push the ball past its top speed either
+    self.velocity = (self.velocity + self.curve * dt).clamp_length(settings.max_ball_speed);
+
+    // A paddle that moved into the side of the ball shoves it out of the way
+    for paddle in paddles {
+      self.push_off_end(paddle, dt);
+    }
+
+    // Only paddles the ball is heading towards can be hit. This also
+    // stops the ball from bouncing back and forth inside a paddle,
+    // and lets it go through the back of a paddle in doubles.
+    let motion = self.velocity * dt;
+    let hit = paddles
+      .iter()
+      .filter_map(|paddle| {
+        let player = paddle.player()?;
+        if !player.side().facing(self.velocity) {
+          return None;
+        }
+        let hit_time = self.bounds().sweep(motion, &paddle.bounds())?;
+        Some((hit_time, player, *paddle))
+      })
+      .min_by(|a, b| a.0.total_cmp(&b.0));
+
+    match hit {
+      Some((hit_time, player, paddle)) => {
+        // Move up to the paddle, bounce, then use up the rest of the step
+        self.position += motion * hit_time;
+        self.bounce_off(paddle, settings);
+
+        // A curve shot bends back against the way it was sent
+        let vertical = player.side().is_vertical();
+        let along = if vertical { self.velocity.y } else { self.velocity.x };
+        let bend = if curving(player) { -along.signum() * CURVE } else { 0.0 };
+        self.curve = if vertical { Vec2::new(0.0, bend) } else { Vec2::new(bend, 0.0) };
+        self.last_hit = Some(player);
+        events.push(Event::PaddleHit(player));
+
+        self.position += self.velocity * (dt * (1.0 - hit_time));
+      },
+      None => self.position += motion,
+    }
+
+    // Keep the ball inside the screen so it can't get stuck flipping
+    // direction every step while it's partly past a wall.
+    // A curve is mirrored too, otherwise it would pull the ball along the wall.
+    let [left, right, top, bottom] = walls;
+    if top && self.position.y <= 0.0 {
+      self.position.y = 0.0;
+      self.velocity.y = self.velocity.y.abs();
+      self.curve.y = self.curve.y.abs();
+      events.push(Event::WallBounce);
+    } else if bottom && self.position.y + self.height() >= settings.window_height {
+      self.position.y = settings.window_height - self.height();
+      self.velocity.y = -self.velocity.y.abs();
+      self.curve.y = -self.curve.y.abs();
+      events.push(Event::WallBounce);
+    }
+    if left && self.position.x <= 0.0 {
+      self.position.x = 0.0;
+      self.velocity.x = self.velocity.x.abs();
+      self.curve.x = self.curve.x.abs();
+      events.push(Event::WallBounce);
+    } else if right && self.position.x + self.width() >= settings.window_width {
+      self.position.x = settings.window_width - self.width();
+      self.velocity.x = -self.velocity.x.abs();
+      self.curve.x = -self.curve.x.abs();
+      events.push(Event::WallBounce);
+    }
+  }
+
+  /// Sends a ball back from `paddle`. Where it hits picks the angle: straight back
+  /// from the middle, up to `max_bounce_angle` off either end, and a moving paddle
+  /// drags it along a bit more. Every hit makes it a little faster, up to `max_ball_speed`.
+  fn bounce_off(&mut self, paddle: &Entity, settings: &Settings) {
+    // Work along the paddle's axes, so the same code works for every side:
+    // `x` is towards the paddle and `y` is the way the paddle moves
+    let vertical = paddle.is_vertical();
+    let axes = |v: Vec2| if vertical { v } else { Vec2::new(v.y, v.x) };
+    let (mut position, velocity, size) = (axes(self.position), axes(self.velocity), axes(self.size));
+    let (paddle_position, paddle_size) = (axes(paddle.position), axes(paddle.size));
+
+    // From -1.0 with the ball just touching one end of the paddle to 1.0 at the
+    // other. A ball that caught the corner counts as hitting the very end.
+    let reach = (paddle_size.y + size.y) / 2.0;
+    let offset = ((axes(self.center()).y - axes(paddle.center()).y) / reach).clamp(-1.0, 1.0);
+
+    let max_angle = settings.max_bounce_angle.to_radians();
+    let speed = (velocity.length() + settings.ball_acc).min(settings.max_ball_speed);
+    let aim = offset * max_angle;
+    let spin = axes(paddle.velocity).y * settings.spin_transfer;
+    // Not `f32::sin` and co, those can round differently on another machine
+    let angle = math::atan2(speed * math::sin(aim) + spin, speed * math::cos(aim)).clamp(-max_angle, max_angle);
+
+    // Back the way it came
+    let away = -velocity.x.signum();
+    let velocity = Vec2::new(away * speed * math::cos(angle), speed * math::sin(angle));
+
+    // Don't leave any of the ball inside the paddle: in front of it for a normal
+    // hit, or past the end it caught
+    let center = position.y + size.y / 2.0;
+    if center < paddle_position.y {
+      position.y = paddle_position.y - size.y;
+    } else if center > paddle_position.y + paddle_size.y {
+      position.y = paddle_position.y + paddle_size.y;
+    } else if away > 0.0 {
+      position.x = paddle_position.x + paddle_size.x;
+    } else {
+      position.x = paddle_position.x - size.x;
+    }
+
+    self.position = axes(position);
+    self.velocity = axes(velocity);
+  }
+
+  /// Paddles move before the ball does, so one that's faster than the ball can end up
+  /// on top of it. If the ball was past either end before the paddle moved it's pushed
+  /// off that end, otherwise the paddle is about to hit it (or it's going through the back).
+  fn push_off_end(&mut self, paddle: &Entity, dt: f32) {
+    if !self.bounds().overlaps(&paddle.bounds()) {
+      return;
+    }
+    let vertical = paddle.is_vertical();
+    let (center, start, length, moved) = if vertical {
+      (self.center().y, paddle.position.y, paddle.height(), paddle.velocity.y * dt)
+    } else {
+      (self.center().x, paddle.position.x, paddle.width(), paddle.velocity.x * dt)
+    };
+    let (along, size) = if vertical { (&mut self.position.y, self.size.y) } else { (&mut self.position.x, self.size.x) };
+    let start_before = start - moved;
+    if center < start_before {
+      *along = start - size;
+    } else if center > start_before + length {
+      *along = start + length;
+    }
+  }
+
+  /// Whether a ball has gone past `side`, into the goal if there is one
+  fn past(&self, side: Side, settings: &Settings) -> bool {
+    match side {
+      Side::Left => self.position.x < 0.0,
+      Side::Right => self.position.x > settings.window_width,
+      Side::Top => self.position.y < 0.0,
+      Side::Bottom => self.position.y > settings.window_height,
+    }
+  }
+}
+
+/// What each paddle is doing this tick, in the same order as `Simulation::paddles`:
+/// -1.0 is up (or left), 1.0 is down (or right) and 0.0 is standing still
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Inputs(pub [f32; MAX_PADDLES]);
+
+/// Squeezes a paddle movement into a single byte, for sending over the network.
+/// Both machines turn it back into exactly the same f32, which keeps them in sync.
+pub fn quantize_input(movement: f32) -> i8 {
+  (movement.clamp(-1.0, 1.0) * 127.0).round() as i8
+}
+
+pub fn dequantize_input(movement: i8) -> f32 {
+  // -128 would be slightly past full speed
+  (movement as f32 / 127.0).max(-1.0)
+}
+
+impl Inputs {
+  /// Inputs for a game with just two paddles
+  pub fn two(player1: f32, player2: f32) -> Inputs {
+    Inputs([player1, player2, 0.0, 0.0])
+  }
+
+  /// Every paddle's movement as bytes. Only inputs that have been through
+  /// this can be replayed exactly, so the game always steps with them.
+  pub fn quantize(self) -> [i8; MAX_PADDLES] {
+    self.0.map(quantize_input)
+  }
+
+  pub fn dequantize(inputs: [i8; MAX_PADDLES]) -> Inputs {
+    Inputs(inputs.map(dequantize_input))
+  }
+}
+
+/// Things that happened during a step, for whoever wants to react to them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+  PaddleHit(Player),
+  WallBounce,
+  PowerUp(Player, PowerUp),
+  Scored(Player),
+  // Only in elimination modes, where letting a ball in costs a life instead
+  LostLife(Player),
+  Eliminated(Player),
+  Won(Player),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Simulation {
+  // Player 1's paddle, player 2's, and so on. Doubles has player 1 and
+  // player 2's back paddles first, then their front paddles.
+  pub paddles: Vec<Entity>,
+  // Points for each player, or lives left in elimination modes
+  pub scores: Vec<i32>,
+  // There's always at least one ball. Multi-ball adds more until the next point.
+  pub balls: Vec<Entity>,
+  pub power_ups: Vec<Entity>,
+  // Power-ups that are still working
+  pub effects: Vec<Effect>,
+  pub winner: Option<Player>,
+  pub settings: Settings,
+  // Seconds of play so far, for the time limit
+  pub elapsed: f32,
+  // Everything random comes from `rng`, so a game with the same seed,
+  // settings and inputs always plays out exactly the same
+  pub seed: u64,
+  rng: Rng,
+  // How big player 1's paddle, player 2's paddle and the ball started out
+  sizes: [Vec2; 3],
+  // How big each paddle started out, they change size with power-ups
+  paddle_sizes: Vec<Vec2>,
+  // Counts down to the next power-up showing up
+  power_up_timer: f32,
+}
+
+impl Simulation {
+  /// Sets up a new game, with the paddles centered on their sides and the ball in the middle.
+  /// Players 3 and 4 use the same paddles as players 1 and 2, turned on their side.
+  pub fn new(settings: Settings, seed: u64, player1_size: Vec2, player2_size: Vec2, ball_size: Vec2) -> Simulation {
+    let width = settings.window_width;
+    let height = settings.window_height;
+    let mode = settings.mode;
+
+    let mut paddles = Vec::new();
+    for index in 0..mode.paddles() {
+      let player = Player::from_index(index % mode.players());
+      let side = player.side();
+      let size = if index % 2 == 0 { player1_size } else { player2_size };
+      let size = if side.is_vertical() { size } else { Vec2::new(size.y, size.x) };
+      let margin = if index < mode.players() { PADDLE_MARGIN } else { width * DOUBLES_FRONT - size.x / 2.0 };
+      // Offset so that the paddle is centered on its side on start up.
+      let position = match side {
+        Side::Left => Vec2::new(margin, (height - size.y) / 2.0),
+        Side::Right => Vec2::new(width - size.x - margin, (height - size.y) / 2.0),
+        Side::Top => Vec2::new((width - size.x) / 2.0, margin),
+        Side::Bottom => Vec2::new((width - size.x) / 2.0, height - size.y - margin),
+      };
+      paddles.push(Entity::paddle(player, size, position));
+    }
+
+    let ball_position = Vec2::new(
+      width / 2.0 - ball_size.x / 2.0,
+      height / 2.0 - ball_size.y / 2.0,
+    );
+    let mut rng = Rng::new(seed);
+    let ball_velocity = serve_velocity(&mut rng, &settings, Side::Left);
+
+    // Points count up from zero, lives count down from the winning score
+    let start = if mode.elimination() { settings.winning_score } else { 0 };
+
+    Simulation {
+      paddle_sizes: paddles.iter().map(|paddle| paddle.size).collect(),
+      paddles,
+      scores: vec![start; mode.players()],
+      balls: vec![Entity::with_velocity(ball_size, ball_position, ball_velocity, EntityType::Ball)],
+      power_ups: Vec::new(),
+      effects: Vec::new(),
+      winner: None,
+      settings,
+      elapsed: 0.0,
+      seed,
+      rng,
+      sizes: [player1_size, player2_size, ball_size],
+      power_up_timer: power_up::SPAWN_INTERVAL,
+    }
+  }
+
+  /// How big player 1's paddle, player 2's paddle and the ball were at the start
+  pub fn sizes(&self) -> [Vec2; 3] {
+    self.sizes
+  }
+
+  pub fn mode(&self) -> Mode {
+    self.settings.mode
+  }
+
+  /// Everyone playing this game, whether they're still in or not
+  pub fn players(&self) -> impl Iterator<Item = Player> {
+    Player::ALL.into_iter().take(self.scores.len())
+  }
+
+  pub fn score(&self, player: Player) -> i32 {
+    self.scores.get(player.index()).copied().unwrap_or_default()
+  }
+
+  /// Whether `player` is playing this game and hasn't been knocked out
+  pub fn in_play(&self, player: Player) -> bool {
+    player.index() < self.scores.len() && (!self.mode().elimination() || self.score(player) > 0)
+  }
+
+  /// Advances the game by `dt` seconds. The game itself always passes
+  /// `TIMESTEP`, so that every step (and every game) plays out the same.
+  pub fn step(&mut self, inputs: Inputs, dt: f32) -> Vec<Event> {
+    let mut events = Vec::new();
+    if self.winner.is_some() {
+      // Nothing moves once the game is over
+      return events;
+    }
+
+    self.elapsed += dt;
+    let settings = self.settings;
+    // Sides nobody is guarding (or anymore) are walls
+    let goals = Side::ALL.map(|side| self.players().any(|player| player.side() == side && self.in_play(player)));
+
+    /*             Player Controls               */
+    for (paddle, movement) in self.paddles.iter_mut().zip(inputs.0) {
+      paddle.move_paddle(movement, dt, &settings);
+    }
+
+    /*             Power-Up Effects               */
+    for effect in self.effects.iter_mut() {
+      effect.remaining -= dt;
+    }
+    self.effects.retain(|effect| effect.remaining > 0.0);
+    self.resize_paddles();
+
+    /*             Ball Controls               */
+    let in_play: Vec<&Entity> = self
+      .paddles
+      .iter()
+      .filter(|paddle| paddle.player().is_some_and(|player| goals[player.side() as usize]))
+      .collect();
+    let effects = &self.effects;
+    let curving = |player| effects.iter().any(|effect| effect.power_up == PowerUp::Curve && effect.player == player);
+    for ball in self.balls.iter_mut() {
+      ball.move_ball(&in_play, goals.map(|goal| !goal), curving, dt, &settings, &mut events);
+    }
+
+    self.collect_power_ups(&mut events);
+    self.spawn_power_ups(dt);
+
+    /*        Score Counting          */
+    // Any ball getting into a goal counts, and the round starts over with one ball
+    let conceded = self
+      .balls
+      .iter()
+      .find_map(|ball| Side::ALL.into_iter().find(|&side| goals[side as usize] && ball.past(side, &settings)));
+    if let Some(side) = conceded {
+      let defender = Player::from_index(side as usize);
+      if self.mode().elimination() {
+        self.scores[defender.index()] -= 1;
+        events.push(Event::LostLife(defender));
+        if self.scores[defender.index()] == 0 {
+          events.push(Event::Eliminated(defender));
+        }
+      } else {
+        // With only two sides, the point goes to the other one
+        let scorer = Player::from_index(1 - defender.index());
+        self.scores[scorer.index()] += 1;
+        events.push(Event::Scored(scorer));
+      }
+      self.reset_round(side.opposite());
+    }
+
+    self.winner = self.leader_if_won();
+    if let Some(winner) = self.winner {
+      events.push(Event::Won(winner));
+    }
+
+    events
+  }
+
+  /// Seconds left before the time limit, if there is one
+  pub fn time_left(&self) -> Option<f32> {
+    let limit = self.settings.time_limit;
+    (limit > 0).then(|| (limit as f32 - self.elapsed).max(0.0))
+  }
+
+  /// Whoever is ahead, if they've done enough to win
+  fn leader_if_won(&self) -> Option<Player> {
+    let best = *self.scores.iter().max()?;
+    let mut leaders = self.players().filter(|&player| self.score(player) == best);
+    let leader = leaders.next()?;
+    if leaders.next().is_some() {
+      // Nobody wins a draw, even when time is up
+      return None;
+    }
+
+    let time_up = self.time_left() == Some(0.0);
+    if self.mode().elimination() {
+      // Last one standing
+      return (time_up || self.players().filter(|&player| self.in_play(player)).count() == 1).then_some(leader);
+    }
+
+    let second = self.players().filter(|&player| player != leader).map(|player| self.score(player)).max();
+    let reached_score = best >= self.settings.winning_score;
+    let far_enough_ahead = !self.settings.win_by_two || best - second.unwrap_or_default() >= 2;
+    (time_up || (reached_score && far_enough_ahead)).then_some(leader)
+  }
+
+  /// Starts the whole game over: scores back to zero and everything in its starting spot
+  pub fn restart(&mut self) {
+    let [player1, player2, ball] = self.sizes;
+    *self = Simulation::new(self.settings, self.seed, player1, player2, ball);
+  }
+
+  /// Sets everything up for the next point, serving towards `towards`
+  fn reset_round(&mut self, towards: Side) {
+    for paddle in self.paddles.iter_mut() {
+      paddle.reset(&self.settings);
+    }
+    // Extra balls from multi-ball only last until someone scores
+    self.balls.truncate(1);
+    let ball = &mut self.balls[0];
+    ball.reset(&self.settings);
+    ball.velocity = serve_velocity(&mut self.rng, &self.settings, towards);
+  }
+
+  /// Makes each paddle as big as its player's effects say it should be
+  fn resize_paddles(&mut self) {
+    for (paddle, size) in self.paddles.iter_mut().zip(&self.paddle_sizes) {
+      let player = paddle.player();
+      let scale: f32 = self
+        .effects
+        .iter()
+        .filter(|effect| Some(effect.player) == player)
+        .map(|effect| match effect.power_up {
+          PowerUp::BigPaddle => BIG_PADDLE,
+          PowerUp::SmallPaddle => SMALL_PADDLE,
+          _ => 1.0,
+        })
+        .product();
+      let scale = scale.clamp(MIN_PADDLE_SCALE, MAX_PADDLE_SCALE);
+      let (length, base) = if paddle.is_vertical() { (paddle.height(), size.y) } else { (paddle.width(), size.x) };
+      if length != base * scale {
+        paddle.resize_paddle(base * scale, &self.settings);
+      }
+    }
+  }
+
+  /// Gives any power-up a ball went through to whoever hit that ball last
+  fn collect_power_ups(&mut self, events: &mut Vec<Event>) {
+    let mut index = 0;
+    while index < self.power_ups.len() {
+      let bounds = self.power_ups[index].bounds();
+      let collector = self
+        .balls
+        .iter()
+        .position(|ball| ball.last_hit.is_some() && ball.bounds().overlaps(&bounds));
+      let Some(ball) = collector else {
+        index += 1;
+        continue;
+      };
+
+      let EntityType::PowerUp(power_up) = self.power_ups.remove(index).entity_type else {
+        unreachable!("only power-ups go in power_ups");
+      };
+      let player = self.balls[ball].last_hit.unwrap_or(Player::One);
+      self.apply_power_up(power_up, player, ball);
+      events.push(Event::PowerUp(player, power_up));
+    }
+  }
+
+  fn apply_power_up(&mut self, power_up: PowerUp, player: Player, ball: usize) {
+    match power_up {
+      PowerUp::BigPaddle | PowerUp::Curve => self.effects.push(Effect {
+        power_up,
+        player,
+        remaining: power_up::EFFECT_DURATION,
+      }),
+      // Everyone else's paddle shrinks
+      PowerUp::SmallPaddle => {
+        let others: Vec<Player> = self.players().filter(|&other| other != player).collect();
+        for other in others {
+          self.effects.push(Effect {
+            power_up,
+            player: other,
+            remaining: power_up::EFFECT_DURATION,
+          });
+        }
+      },
+      PowerUp::MultiBall => {
+        // The new balls split off at an angle on either side of the old one
+        let original = self.balls[ball].clone();
+        let split = self.settings.ball_speed * SERVE_ANGLE;
+        let sideways = if original.velocity.x.abs() >= original.velocity.y.abs() {
+          Vec2::new(0.0, split)
+        } else {
+          Vec2::new(split, 0.0)
+        };
+        for direction in [-1.0, 1.0] {
+          if self.balls.len() < MAX_BALLS {
+            let mut extra = original.clone();
+            extra.velocity += sideways * direction;
+            self.balls.push(extra);
+          }
+        }
+      },
+      PowerUp::SpeedUp => {
+        let ball = &mut self.balls[ball];
+        ball.velocity = (ball.velocity * SPEED_UP).clamp_length(self.settings.max_ball_speed);
+      },
+    }
+    self.resize_paddles();
+  }
+
+  /// Every so often a random power-up shows up somewhere in the middle of the field
+  fn spawn_power_ups(&mut self, dt: f32) {
+    let enabled: Vec<PowerUp> = power_up::ALL.into_iter().filter(|kind| kind.enabled(&self.settings)).collect();
+    if enabled.is_empty() {
+      return;
+    }
+
+    self.power_up_timer -= dt;
+    if self.power_up_timer > 0.0 {
+      return;
+    }
+    self.power_up_timer = power_up::SPAWN_INTERVAL;
+    if self.power_ups.len() >= power_up::MAX_ON_FIELD {
+      return;
+    }
+
+    let (width, height) = (self.settings.window_width, self.settings.window_height);
+    let kind = enabled[(self.rng.next_u64() % enabled.len() as u64) as usize];
+    let position = Vec2::new(
+      self.rng.range(width * 0.3, width * 0.7 - power_up::SIZE),
+      self.rng.range(height * 0.3, height * 0.7 - power_up::SIZE),
+    );
+    let size = Vec2::new(power_up::SIZE, power_up::SIZE);
+    self.power_ups.push(Entity::with_velocity(size, position, Vec2::zero(), EntityType::PowerUp(kind)));
+  }
+}
+
+/// A serve heading towards `side` at a random angle, so they don't all go straight across
+fn serve_velocity(rng: &mut Rng, settings: &Settings, side: Side) -> Vec2 {
+  let speed = settings.ball_speed;
+  let angle = rng.range(-SERVE_ANGLE, SERVE_ANGLE) * speed;
+  match side {
+    Side::Left => Vec2::new(-speed, angle),
+    Side::Right => Vec2::new(speed, angle),
+    Side::Top => Vec2::new(angle, -speed),
+    Side::Bottom => Vec2::new(angle, speed),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Same sizes as the textures in ./resources
+  const PADDLE: Vec2 = Vec2::new(24.0, 104.0);
+  const BALL: Vec2 = Vec2::new(22.0, 22.0);
+
+  fn simulation() -> Simulation {
+    Simulation::new(Settings::default(), 1, PADDLE, PADDLE, BALL)
+  }
+
+  /// Puts the ball past player 2, so player 1 scores on the next step
+  fn score_for_player1(sim: &mut Simulation) -> Vec<Event> {
+    sim.balls[0].position.x = sim.settings.window_width + 10.0;
+    sim.step(Inputs::default(), TIMESTEP)
+  }
+
+  /// Steps until something happens, or gives up after a minute of game time
+  fn step_until_event(sim: &mut Simulation, inputs: Inputs) -> Vec<Event> {
+    for _ in 0..60 * 60 {
+      let events = sim.step(inputs, TIMESTEP);
+      if !events.is_empty() {
+        return events;
+      }
+    }
+    Vec::new()
+  }
+
+  #[test]
+  fn paddles_stay_on_screen() {
+    let mut sim = simulation();
+    for _ in 0..100 {
+      sim.step(Inputs::two(-1.0, 1.0), TIMESTEP);
+    }
+    assert_eq!(0.0, sim.paddles[0].position.y);
+    assert_eq!(sim.settings.window_height - PADDLE.y, sim.paddles[1].position.y);
+  }
+
+  #[test]
+  fn movement_doesnt_depend_on_step_size() {
+    let inputs = Inputs::two(-1.0, 0.0);
+    let mut once = simulation();
+    once.step(inputs, TIMESTEP);
+    let mut twice = simulation();
+    twice.step(inputs, TIMESTEP / 2.0);
+    twice.step(inputs, TIMESTEP / 2.0);
+
+    assert!((once.paddles[0].position.y - twice.paddles[0].position.y).abs() < 0.001);
+    assert!((once.balls[0].position.x - twice.balls[0].position.x).abs() < 0.001);
+  }
+
+  /// Puts the ball just in front of player 1's paddle, `offset` below its center, heading straight at it
+  fn ball_in_front_of_player1(sim: &mut Simulation, offset: f32) {
+    sim.balls[0].position = Vec2::new(
+      sim.paddles[0].position.x + PADDLE.x + 2.0,
+      sim.paddles[0].center().y - BALL.y / 2.0 + offset,
+    );
+    sim.balls[0].velocity = Vec2::new(-sim.settings.ball_speed, 0.0);
+  }
+
+  /// The ball's angle from straight across, in degrees
+  fn bounce_angle(ball: &Entity) -> f32 {
+    ball.velocity.y.atan2(ball.velocity.x.abs()).to_degrees()
+  }
+
+  #[test]
+  fn bounce_angle_depends_on_where_the_ball_hits() {
+    let mut sim = simulation();
+    ball_in_front_of_player1(&mut sim, 16.0);
+
+    let events = sim.step(Inputs::default(), TIMESTEP);
+
+    assert_eq!(vec![Event::PaddleHit(Player::One)], events);
+    let speed = sim.settings.ball_speed + sim.settings.ball_acc;
+    assert!((sim.balls[0].velocity.length() - speed).abs() < 0.01);
+    assert!(sim.balls[0].velocity.x > 0.0);
+    assert!(sim.balls[0].velocity.y > 0.0, "hitting below the center should send the ball down");
+    assert!(sim.balls[0].position.x >= sim.paddles[0].position.x + PADDLE.x, "ball ended up inside the paddle");
+
+    // The middle sends it straight back, the very end at the steepest angle
+    let mut middle = simulation();
+    ball_in_front_of_player1(&mut middle, 0.0);
+    middle.step(Inputs::default(), TIMESTEP);
+    assert_eq!(0.0, bounce_angle(&middle.balls[0]));
+    let mut end = simulation();
+    ball_in_front_of_player1(&mut end, -(PADDLE.y + BALL.y) / 2.0 + 1.0);
+    end.step(Inputs::default(), TIMESTEP);
+    assert!(bounce_angle(&end.balls[0]) < -55.0);
+  }
+
+  #[test]
+  fn moving_paddles_add_spin_up_to_the_max_angle() {
+    let mut sim = simulation();
+    ball_in_front_of_player1(&mut sim, 0.0);
+    sim.balls[0].position.y += sim.settings.paddle_speed * TIMESTEP;
+
+    // The paddle moves down into the ball, and drags it down with it
+    let events = sim.step(Inputs::two(1.0, 0.0), TIMESTEP);
+    assert_eq!(vec![Event::PaddleHit(Player::One)], events);
+    assert!(sim.balls[0].velocity.y > 0.0);
+
+    // Even right at the end of a moving paddle it can't go any steeper than the max
+    let mut sim = simulation();
+    ball_in_front_of_player1(&mut sim, (PADDLE.y + BALL.y) / 2.0 - 1.0);
+    sim.balls[0].position.y += sim.settings.paddle_speed * TIMESTEP;
+    sim.step(Inputs::two(1.0, 0.0), TIMESTEP);
+    assert!((bounce_angle(&sim.balls[0]) - sim.settings.max_bounce_angle).abs() < 0.01);
+  }
+
+  #[test]
+  fn ball_speed_is_capped() {
+    let mut sim = simulation();
+    ball_in_front_of_player1(&mut sim, 0.0);
+    sim.balls[0].velocity.x = -sim.settings.max_ball_speed;
+
+    sim.step(Inputs::default(), TIMESTEP);
+    assert_eq!(sim.settings.max_ball_speed, sim.balls[0].velocity.length());
+
+    sim.apply_power_up(PowerUp::SpeedUp, Player::One, 0);
+    assert_eq!(sim.settings.max_ball_speed, sim.balls[0].velocity.length());
+  }
+
+  #[test]
+  fn corner_hits_dont_leave_the_ball_inside_the_paddle() {
+    let mut sim = simulation();
+    let paddle = sim.paddles[0].bounds();
+    // Coming down steeply onto the top corner, while the paddle moves up to meet it
+    sim.balls[0].position = Vec2::new(paddle.x + paddle.width - BALL.x / 2.0, paddle.y - BALL.y - 20.0);
+    sim.balls[0].velocity = Vec2::new(-60.0, 300.0);
+
+    let mut hit = false;
+    for _ in 0..30 {
+      let events = sim.step(Inputs::two(-1.0, 0.0), TIMESTEP);
+      hit |= events.contains(&Event::PaddleHit(Player::One));
+      assert!(!sim.balls[0].bounds().overlaps(&sim.paddles[0].bounds()), "ball ended up inside the paddle");
+    }
+    assert!(hit);
+    assert!(sim.balls[0].velocity.x > 0.0);
+  }
+
+  #[test]
+  fn fast_ball_doesnt_tunnel_through_paddle() {
+    let mut sim = simulation();
+    // Fast enough to move further than the paddle and ball are wide in one step
+    sim.balls[0].velocity = Vec2::new(-10_000.0, 0.0);
+    sim.balls[0].position = Vec2::new(
+      sim.paddles[0].position.x + PADDLE.x + 10.0,
+      sim.paddles[0].center().y - BALL.y / 2.0,
+    );
+
+    let events = sim.step(Inputs::default(), TIMESTEP);
+
+    assert_eq!(vec![Event::PaddleHit(Player::One)], events);
+    assert!(sim.balls[0].velocity.x > 0.0);
+    assert_eq!(0, sim.scores[1]);
+  }
+
+  #[test]
+  fn ball_bounces_off_walls() {
+    let mut sim = simulation();
+    sim.balls[0].position.y = 1.0;
+    sim.balls[0].velocity.y = -120.0;
+
+    let events = sim.step(Inputs::default(), TIMESTEP);
+
+    assert_eq!(vec![Event::WallBounce], events);
+    assert_eq!(120.0, sim.balls[0].velocity.y);
+    assert_eq!(0.0, sim.balls[0].position.y);
+  }
+
+  #[test]
+  fn scoring_resets_the_round() {
+    let mut sim = simulation();
+    sim.paddles[0].position.y = 0.0;
+    sim.balls[0].velocity.y = 0.0;
+
+    let events = step_until_event(&mut sim, Inputs::default());
+
+    assert_eq!(vec![Event::Scored(Player::Two)], events);
+    assert_eq!(1, sim.scores[1]);
+    assert_eq!(simulation().balls[0].position, sim.balls[0].position);
+    assert_eq!(simulation().paddles[0].position, sim.paddles[0].position);
+  }
+
+  #[test]
+  fn first_to_winning_score_wins() {
+    let mut sim = simulation();
+    sim.scores[0] = sim.settings.winning_score - 1;
+
+    let events = score_for_player1(&mut sim);
+
+    assert_eq!(vec![Event::Scored(Player::One), Event::Won(Player::One)], events);
+    assert_eq!(Some(Player::One), sim.winner);
+    assert!(sim.step(Inputs::two(1.0, 1.0), TIMESTEP).is_empty());
+  }
+
+  #[test]
+  fn win_by_two_plays_on_until_two_ahead() {
+    let mut sim = simulation();
+    sim.settings.win_by_two = true;
+    sim.scores[0] = sim.settings.winning_score - 1;
+    sim.scores[1] = sim.settings.winning_score - 1;
+
+    score_for_player1(&mut sim);
+    assert_eq!(None, sim.winner);
+    score_for_player1(&mut sim);
+    assert_eq!(Some(Player::One), sim.winner);
+  }
+
+  #[test]
+  fn time_limit_ends_the_game() {
+    let mut sim = simulation();
+    sim.settings.time_limit = 60;
+    sim.elapsed = 60.0;
+    sim.scores[1] = 1;
+
+    sim.step(Inputs::default(), TIMESTEP);
+    assert_eq!(Some(Player::Two), sim.winner);
+  }
+
+  #[test]
+  fn draw_at_time_limit_goes_to_next_point() {
+    let mut sim = simulation();
+    sim.settings.time_limit = 60;
+    sim.elapsed = 60.0;
+
+    sim.step(Inputs::default(), TIMESTEP);
+    assert_eq!(None, sim.winner);
+    assert_eq!(vec![Event::Scored(Player::One), Event::Won(Player::One)], score_for_player1(&mut sim));
+  }
+
+  #[test]
+  fn same_seed_same_serves() {
+    let mut one = simulation();
+    let mut other = simulation();
+    assert_eq!(one, other);
+
+    score_for_player1(&mut one);
+    score_for_player1(&mut other);
+    assert_eq!(one.balls[0].velocity, other.balls[0].velocity);
+    assert_ne!(0.0, one.balls[0].velocity.y);
+  }
+
+  #[test]
+  fn quantized_inputs_survive_the_round_trip() {
+    let inputs = Inputs::two(-1.0, 0.5);
+    let once = Inputs::dequantize(inputs.quantize());
+    assert_eq!(-1.0, once.0[0]);
+    assert_eq!(once, Inputs::dequantize(once.quantize()));
+  }
+
+  /// Puts a power-up right where the ball is, as if player 1 had just hit it there
+  fn collect(sim: &mut Simulation, kind: PowerUp) -> Vec<Event> {
+    let ball = &mut sim.balls[0];
+    ball.last_hit = Some(Player::One);
+    let power_up = Entity::with_velocity(ball.size, ball.position, Vec2::zero(), EntityType::PowerUp(kind));
+    sim.power_ups.push(power_up);
+    sim.step(Inputs::default(), TIMESTEP)
+  }
+
+  #[test]
+  fn power_up_goes_to_the_last_hitter() {
+    let mut sim = simulation();
+
+    assert_eq!(vec![Event::PowerUp(Player::One, PowerUp::BigPaddle)], collect(&mut sim, PowerUp::BigPaddle));
+    assert!(sim.power_ups.is_empty());
+    assert_eq!(PADDLE.y * BIG_PADDLE, sim.paddles[0].height());
+    assert_eq!(PADDLE.y, sim.paddles[1].height());
+
+    // Until it wears off
+    for _ in 0..(power_up::EFFECT_DURATION / TIMESTEP) as usize + 1 {
+      sim.step(Inputs::default(), TIMESTEP);
+    }
+    assert!(sim.effects.is_empty());
+    assert_eq!(PADDLE.y, sim.paddles[0].height());
+  }
+
+  #[test]
+  fn served_ball_doesnt_collect_power_ups() {
+    let mut sim = simulation();
+    let ball = &sim.balls[0];
+    let power_up = Entity::with_velocity(ball.size, ball.position, Vec2::zero(), EntityType::PowerUp(PowerUp::SpeedUp));
+    sim.power_ups.push(power_up);
+
+    assert!(sim.step(Inputs::default(), TIMESTEP).is_empty());
+    assert_eq!(1, sim.power_ups.len());
+  }
+
+  #[test]
+  fn multi_ball_lasts_until_the_next_point() {
+    let mut sim = simulation();
+
+    collect(&mut sim, PowerUp::MultiBall);
+    assert_eq!(3, sim.balls.len());
+    assert_ne!(sim.balls[1].velocity, sim.balls[2].velocity);
+
+    sim.balls[2].position.x = sim.settings.window_width + 10.0;
+    assert_eq!(vec![Event::Scored(Player::One)], sim.step(Inputs::default(), TIMESTEP));
+    assert_eq!(1, sim.balls.len());
+  }
+
+  #[test]
+  fn power_ups_only_show_up_when_turned_on() {
+    let mut off = simulation();
+    let mut on = simulation();
+    on.settings.power_ups = true;
+    on.settings.big_paddle = false;
+    on.settings.small_paddle = false;
+    on.settings.speed_up = false;
+    on.settings.curve_shots = false;
+
+    for _ in 0..(power_up::SPAWN_INTERVAL / TIMESTEP) as usize + 1 {
+      off.step(Inputs::default(), TIMESTEP);
+      on.step(Inputs::default(), TIMESTEP);
+    }
+    assert!(off.power_ups.is_empty());
+    assert_eq!(1, on.power_ups.len());
+    assert_eq!(EntityType::PowerUp(PowerUp::MultiBall), on.power_ups[0].entity_type);
+  }
+
+  fn simulation_with_mode(mode: Mode) -> Simulation {
+    let settings = Settings { mode, ..Settings::default() };
+    Simulation::new(settings, 1, PADDLE, PADDLE, BALL)
+  }
+
+  #[test]
+  fn every_mode_has_a_paddle_on_each_players_side() {
+    for mode in crate::settings::MODES {
+      let sim = simulation_with_mode(mode);
+      assert_eq!(mode.paddles(), sim.paddles.len());
+      assert_eq!(mode.players(), sim.scores.len());
+      for (index, paddle) in sim.paddles.iter().enumerate() {
+        let player = Player::from_index(index % mode.players());
+        assert_eq!(Some(player), paddle.player());
+        assert_eq!(player.side().is_vertical(), paddle.is_vertical());
+      }
+    }
+  }
+
+  #[test]
+  fn last_player_standing_wins() {
+    let mut sim = simulation_with_mode(Mode::ThreeWay);
+    sim.scores = vec![1, 1, 2];
+
+    // Past player 3 at the top
+    sim.balls[0].position.y = -BALL.y - 10.0;
+    let events = sim.step(Inputs::default(), TIMESTEP);
+    assert_eq!(vec![Event::LostLife(Player::Three)], events);
+    assert_eq!(1, sim.score(Player::Three));
+
+    sim.balls[0].position.x = sim.settings.window_width + 10.0;
+    let events = sim.step(Inputs::default(), TIMESTEP);
+    assert_eq!(vec![Event::LostLife(Player::Two), Event::Eliminated(Player::Two)], events);
+    assert!(!sim.in_play(Player::Two));
+    assert_eq!(None, sim.winner);
+
+    sim.balls[0].position.x = -BALL.x - 10.0;
+    let events = sim.step(Inputs::default(), TIMESTEP);
+    assert_eq!(
+      vec![Event::LostLife(Player::One), Event::Eliminated(Player::One), Event::Won(Player::Three)],
+      events
+    );
+  }
+
+  #[test]
+  fn knocked_out_sides_become_walls() {
+    let mut sim = simulation_with_mode(Mode::FourWay);
+    sim.scores[1] = 0;
+    sim.balls[0].velocity = Vec2::new(sim.settings.ball_speed, 0.0);
+    sim.balls[0].position.y = 0.0;
+
+    let events = step_until_event(&mut sim, Inputs::default());
+
+    assert_eq!(vec![Event::WallBounce], events);
+    assert!(sim.balls[0].velocity.x < 0.0);
+  }
+
+  #[test]
+  fn restart_clears_the_winner() {
+    let mut sim = simulation();
+    sim.scores[1] = sim.settings.winning_score;
+    sim.winner = Some(Player::Two);
+    sim.paddles[0].position.y = 0.0;
+
+    sim.restart();
+
+    assert_eq!(simulation(), sim);
+  }
+}
@@ -0,0 +1,215 @@
+// Stats for everyone who plays on this computer, each under their own
+// profile name. They're saved next to the settings file after every game
+// that's played to the end, and the title screen has a leaderboard.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+use crate::simulation::{Event, Simulation};
+
+// How many profiles the leaderboard shows
+pub const LEADERBOARD_SIZE: usize = 8;
+
+/// Everything one profile has done so far
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Record {
+  pub wins: u32,
+  pub losses: u32,
+  // Most paddle hits between two points
+  pub longest_rally: u32,
+  // In pixels per second, measured when it hit a paddle
+  pub fastest_ball: f32,
+  // In seconds
+  pub longest_match: f32,
+  pub time_played: f32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stats {
+  // Whose games count at the moment, kept for the next time the game starts
+  pub profile: String,
+  pub profiles: BTreeMap<String, Record>,
+}
+
+impl Default for Stats {
+  fn default() -> Stats {
+    Stats { profile: String::from("Player"), profiles: BTreeMap::new() }
+  }
+}
+
+impl Stats {
+  /// Where the stats are saved: `stats.toml` next to the settings file
+  pub fn path() -> Option<PathBuf> {
+    Settings::path().map(|path| path.with_file_name("stats.toml"))
+  }
+
+  /// Reads the stats file. A missing file just means nobody has played yet.
+  pub fn load(path: &Path) -> Result<Stats, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+      Ok(contents) => Stats::from_toml(&contents),
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Stats::default()),
+      Err(err) => Err(err.into()),
+    }
+  }
+
+  pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+      fs::create_dir_all(dir)?;
+    }
+    fs::write(path, self.to_toml()?)?;
+    Ok(())
+  }
+
+  pub fn from_toml(contents: &str) -> Result<Stats, Box<dyn Error>> {
+    let stats: Stats = toml::from_str(contents)?;
+    if stats.profile.trim().is_empty() {
+      return Err("the profile needs a name".into());
+    }
+    Ok(stats)
+  }
+
+  pub fn to_toml(&self) -> Result<String, Box<dyn Error>> {
+    Ok(toml::to_string(self)?)
+  }
+
+  /// Switches to the profile called `name`, which is created by its first game
+  pub fn select(&mut self, name: &str) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() {
+      return Err(String::from("the profile needs a name"));
+    }
+    self.profile = name.to_string();
+    Ok(())
+  }
+
+  /// Switches to the next profile that has played before, in alphabetical order
+  pub fn next_profile(&mut self) {
+    let next = self
+      .profiles
+      .keys()
+      .find(|name| **name > self.profile)
+      .or_else(|| self.profiles.keys().next());
+    if let Some(name) = next {
+      self.profile = name.clone();
+    }
+  }
+
+  /// The current profile's stats, all zeros if it hasn't played yet
+  pub fn record(&self) -> Record {
+    self.profiles.get(&self.profile).copied().unwrap_or_default()
+  }
+
+  /// Adds a game that was played to the end to the current profile
+  pub fn finish(&mut self, tally: &Tally, won: bool, duration: f32) {
+    let record = self.profiles.entry(self.profile.clone()).or_default();
+    if won {
+      record.wins += 1;
+    } else {
+      record.losses += 1;
+    }
+    record.longest_rally = record.longest_rally.max(tally.longest_rally);
+    record.fastest_ball = record.fastest_ball.max(tally.fastest_ball);
+    record.longest_match = record.longest_match.max(duration);
+    record.time_played += duration;
+  }
+
+  /// The profiles with the most wins (and then the fewest losses) first
+  pub fn leaderboard(&self) -> Vec<(&str, Record)> {
+    let mut profiles: Vec<(&str, Record)> = self.profiles.iter().map(|(name, record)| (name.as_str(), *record)).collect();
+    profiles.sort_by(|a, b| b.1.wins.cmp(&a.1.wins).then(a.1.losses.cmp(&b.1.losses)));
+    profiles.truncate(LEADERBOARD_SIZE);
+    profiles
+  }
+}
+
+/// Keeps count during a game, for the stats at the end of it
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Tally {
+  // Paddle hits since the last point
+  pub rally: u32,
+  pub longest_rally: u32,
+  pub fastest_ball: f32,
+}
+
+impl Tally {
+  /// Call after every step with what happened during it
+  pub fn update(&mut self, events: &[Event], sim: &Simulation) {
+    for event in events {
+      match event {
+        Event::PaddleHit(_) => {
+          self.rally += 1;
+          self.longest_rally = self.longest_rally.max(self.rally);
+          let fastest = sim.balls.iter().map(|ball| ball.velocity.length()).fold(0.0, f32::max);
+          self.fastest_ball = self.fastest_ball.max(fastest);
+        },
+        Event::Scored(_) | Event::LostLife(_) => self.rally = 0,
+        _ => {},
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::simulation::{Player, Vec2};
+
+  #[test]
+  fn survives_saving_and_loading() {
+    let mut stats = Stats::default();
+    stats.select("Sam Smith").unwrap();
+    stats.finish(&Tally { rally: 0, longest_rally: 7, fastest_ball: 512.5 }, true, 95.0);
+    let contents = stats.to_toml().unwrap();
+
+    assert_eq!(stats, Stats::from_toml(&contents).unwrap());
+    assert_eq!(Stats::default(), Stats::from_toml("").unwrap());
+    assert!(Stats::from_toml("profile = \" \"").is_err());
+  }
+
+  #[test]
+  fn rallies_count_paddle_hits_between_points() {
+    let sim = Simulation::new(Settings::default(), 1, Vec2::new(24.0, 104.0), Vec2::new(24.0, 104.0), Vec2::new(22.0, 22.0));
+    let mut tally = Tally::default();
+    let hit = [Event::PaddleHit(Player::One)];
+
+    tally.update(&hit, &sim);
+    tally.update(&hit, &sim);
+    tally.update(&[Event::Scored(Player::Two)], &sim);
+    tally.update(&hit, &sim);
+
+    assert_eq!(1, tally.rally);
+    assert_eq!(2, tally.longest_rally);
+    assert_eq!(sim.balls[0].velocity.length(), tally.fastest_ball);
+  }
+
+  #[test]
+  fn leaderboard_puts_the_most_wins_first() {
+    let mut stats = Stats::default();
+    let tally = Tally::default();
+    for (name, wins, losses) in [("Ann", 1, 0), ("Bob", 3, 2), ("Cat", 3, 1)] {
+      stats.select(name).unwrap();
+      for _ in 0..wins {
+        stats.finish(&tally, true, 60.0);
+      }
+      for _ in 0..losses {
+        stats.finish(&tally, false, 60.0);
+      }
+    }
+
+    let names: Vec<&str> = stats.leaderboard().iter().map(|(name, _)| *name).collect();
+    assert_eq!(vec!["Cat", "Bob", "Ann"], names);
+    assert_eq!(240.0, stats.record().time_played);
+
+    stats.next_profile();
+    assert_eq!("Ann", stats.profile);
+    stats.next_profile();
+    assert_eq!("Bob", stats.profile);
+  }
+}
@@ -0,0 +1,268 @@
+// Extra effects that make the game feel livelier: sparks flying off the
+// paddles and walls, a trail behind the ball and the screen shaking when
+// someone scores. None of it touches the simulation, it only looks at what
+// happened during each step, so turning it off doesn't change how a game
+// plays (or replays).
+
+use std::collections::VecDeque;
+
+use tetra::graphics::{Color, DrawParams};
+use tetra::math::Vec2;
+use tetra::Context;
+
+use crate::resources::Resources;
+use crate::rng::Rng;
+use crate::settings::Settings;
+use crate::simulation::{Entity, Event, Player, Simulation, TIMESTEP};
+
+// Sparks are little squares this wide
+pub const SPARK_SIZE: f32 = 4.0;
+// How many sparks a bounce makes, and how long they last in seconds
+const PADDLE_SPARKS: usize = 16;
+const WALL_SPARKS: usize = 6;
+const SPARK_LIFETIME: f32 = 0.5;
+const SPARK_SPEED: f32 = 240.0;
+// How many of each ball's last positions are kept for its trail, one per step
+const TRAIL_LENGTH: usize = 12;
+// How far the screen moves when it shakes, in pixels, and for how many seconds
+const SHAKE_STRENGTH: f32 = 8.0;
+const SHAKE_TIME: f32 = 0.4;
+
+struct Spark {
+  position: Vec2<f32>,
+  velocity: Vec2<f32>,
+  color: Color,
+  // Seconds left, it fades out as this runs down
+  life: f32,
+}
+
+pub struct Visuals {
+  sparks: Vec<Spark>,
+  // Where each ball has been lately, newest first
+  trails: Vec<VecDeque<Vec2<f32>>>,
+  // Seconds of shaking left
+  shake: f32,
+  // The simulation's random numbers have to stay the same for every game
+  // with the same seed, so the sparks get their own
+  rng: Rng,
+}
+
+impl Visuals {
+  pub fn new() -> Visuals {
+    Visuals { sparks: Vec::new(), trails: Vec::new(), shake: 0.0, rng: Rng::from_time() }
+  }
+
+  /// Gets rid of everything, for when a new game starts
+  pub fn clear(&mut self) {
+    self.sparks.clear();
+    self.trails.clear();
+    self.shake = 0.0;
+  }
+
+  /// Call on every update, the sparks keep flying and the screen settles
+  /// down even when the game is paused or waiting for a serve
+  pub fn update(&mut self) {
+    for spark in self.sparks.iter_mut() {
+      spark.position += spark.velocity * TIMESTEP;
+      spark.life -= TIMESTEP;
+    }
+    self.sparks.retain(|spark| spark.life > 0.0);
+    self.shake = (self.shake - TIMESTEP).max(0.0);
+  }
+
+  /// Call after every step of the simulation, with how it was before the step
+  pub fn react(&mut self, previous: &Simulation, sim: &Simulation, events: &[Event]) {
+    // After a point the ball is put back in the middle, it didn't fly there
+    if events.iter().any(|event| matches!(event, Event::Scored(_) | Event::LostLife(_))) {
+      self.shake = SHAKE_TIME;
+      self.trails.clear();
+      return;
+    }
+
+    self.sparks_for_bounces(previous, sim, events);
+
+    // Balls from multi-ball start without a trail
+    self.trails.resize_with(sim.balls.len(), VecDeque::new);
+    for (trail, ball) in self.trails.iter_mut().zip(&sim.balls) {
+      trail.push_front(Vec2::from(ball.position));
+      trail.truncate(TRAIL_LENGTH);
+    }
+  }
+
+  /// Events don't say which ball bounced where, so each paddle hit goes to the
+  /// ball closest to that player's paddles, and any other ball that turned
+  /// around must have hit a wall
+  fn sparks_for_bounces(&mut self, previous: &Simulation, sim: &Simulation, events: &[Event]) {
+    let mut hit_paddle = vec![false; sim.balls.len()];
+    for event in events {
+      let Event::PaddleHit(player) = *event else {
+        continue;
+      };
+      let distance = |ball: &Entity| distance_to_paddles(ball, player, sim);
+      let nearest = (0..sim.balls.len()).min_by(|&a, &b| distance(&sim.balls[a]).total_cmp(&distance(&sim.balls[b])));
+      if let Some(index) = nearest {
+        hit_paddle[index] = true;
+        self.burst(&sim.balls[index], Color::rgb(1.0, 0.9, 0.5), PADDLE_SPARKS);
+      }
+    }
+
+    for (index, ball) in sim.balls.iter().enumerate() {
+      let Some(before) = previous.balls.get(index) else {
+        continue;
+      };
+      let turned = before.velocity.x.signum() != ball.velocity.x.signum()
+        || before.velocity.y.signum() != ball.velocity.y.signum();
+      if turned && !hit_paddle[index] {
+        self.burst(ball, Color::WHITE, WALL_SPARKS);
+      }
+    }
+  }
+
+  /// Sprays sparks out of `ball`, mostly the way it's going now
+  fn burst(&mut self, ball: &Entity, color: Color, count: usize) {
+    let center = ball.center();
+    let heading = ball.velocity.y.atan2(ball.velocity.x);
+    for _ in 0..count {
+      let angle = heading + self.rng.range(-0.8, 0.8);
+      let speed = SPARK_SPEED * self.rng.range(0.3, 1.0);
+      self.sparks.push(Spark {
+        position: Vec2::new(center.x, center.y),
+        velocity: Vec2::new(angle.cos(), angle.sin()) * speed,
+        color,
+        life: SPARK_LIFETIME * self.rng.range(0.5, 1.0),
+      });
+    }
+  }
+
+  /// How far to move the whole screen this frame
+  pub fn shake_offset(&mut self) -> Vec2<f32> {
+    if self.shake <= 0.0 {
+      return Vec2::zero();
+    }
+    // Dies down as it runs out
+    let strength = SHAKE_STRENGTH * self.shake / SHAKE_TIME;
+    Vec2::new(self.rng.range(-strength, strength), self.rng.range(-strength, strength))
+  }
+
+  /// Draws the trails and sparks, underneath everything else
+  pub fn draw(&self, ctx: &mut Context, resources: &Resources, sim: &Simulation) {
+    // The balls aren't drawn once the game is over, neither are their trails
+    if sim.winner.is_none() {
+      for (trail, ball) in self.trails.iter().zip(&sim.balls) {
+        let half = Vec2::new(ball.width() / 2.0, ball.height() / 2.0);
+        let length = trail_length(ball, &sim.settings);
+        // The newest one is right under the ball
+        for (age, &position) in trail.iter().enumerate().take(length).skip(1) {
+          // Older positions are smaller and fainter
+          let fade = 1.0 - age as f32 / length as f32;
+          let params = DrawParams::new()
+            .position(position + half)
+            .origin(half)
+            .scale(Vec2::broadcast(fade))
+            .color(Color::WHITE.with_alpha(fade * 0.5));
+          resources.ball.draw(ctx, params);
+        }
+      }
+    }
+
+    for spark in &self.sparks {
+      let params = DrawParams::new()
+        .position(spark.position)
+        .origin(Vec2::broadcast(SPARK_SIZE / 2.0))
+        .color(spark.color.with_alpha(spark.life / SPARK_LIFETIME));
+      resources.spark.draw(ctx, params);
+    }
+  }
+}
+
+fn distance_to_paddles(ball: &Entity, player: Player, sim: &Simulation) -> f32 {
+  sim
+    .paddles
+    .iter()
+    .filter(|paddle| paddle.player() == Some(player))
+    .map(|paddle| (paddle.center() - ball.center()).length())
+    .fold(f32::INFINITY, f32::min)
+}
+
+/// How many positions of `ball`'s trail to draw. A freshly served ball leaves
+/// a short trail, and it gets longer as the ball speeds up.
+fn trail_length(ball: &Entity, settings: &Settings) -> usize {
+  let speed = ball.velocity.length() / settings.ball_speed;
+  ((speed * 5.0) as usize).min(TRAIL_LENGTH)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::simulation::{self, Inputs};
+
+  // Same sizes as the textures in ./resources
+  const PADDLE: simulation::Vec2 = simulation::Vec2::new(24.0, 104.0);
+  const BALL: simulation::Vec2 = simulation::Vec2::new(22.0, 22.0);
+
+  fn simulation() -> Simulation {
+    Simulation::new(Settings::default(), 1, PADDLE, PADDLE, BALL)
+  }
+
+  /// Steps the simulation (and the visuals along with it) until something happens
+  fn step_until_event(visuals: &mut Visuals, sim: &mut Simulation) -> Vec<Event> {
+    for _ in 0..60 * 60 {
+      let previous = sim.clone();
+      let events = sim.step(Inputs::default(), TIMESTEP);
+      visuals.react(&previous, sim, &events);
+      if !events.is_empty() {
+        return events;
+      }
+    }
+    Vec::new()
+  }
+
+  #[test]
+  fn sparks_fly_off_paddles_and_fade_away() {
+    let mut visuals = Visuals::new();
+    let mut sim = simulation();
+    // Straight at player 1's paddle
+    sim.balls[0].position.y = sim.paddles[0].center().y - BALL.y / 2.0;
+    sim.balls[0].velocity.y = 0.0;
+
+    let events = step_until_event(&mut visuals, &mut sim);
+
+    assert_eq!(vec![Event::PaddleHit(Player::One)], events);
+    assert_eq!(PADDLE_SPARKS, visuals.sparks.len());
+    for _ in 0..(SPARK_LIFETIME / TIMESTEP) as usize + 1 {
+      visuals.update();
+    }
+    assert!(visuals.sparks.is_empty());
+  }
+
+  #[test]
+  fn scoring_shakes_the_screen_and_clears_trails() {
+    let mut visuals = Visuals::new();
+    let mut sim = simulation();
+    sim.paddles[0].position.y = 0.0;
+    sim.balls[0].velocity.y = 0.0;
+
+    let events = step_until_event(&mut visuals, &mut sim);
+
+    assert_eq!(vec![Event::Scored(Player::Two)], events);
+    assert!(visuals.trails.is_empty());
+    assert_ne!(Vec2::zero(), visuals.shake_offset());
+    for _ in 0..(SHAKE_TIME / TIMESTEP) as usize + 1 {
+      visuals.update();
+    }
+    assert_eq!(Vec2::zero(), visuals.shake_offset());
+  }
+
+  #[test]
+  fn faster_balls_leave_longer_trails() {
+    let sim = simulation();
+    let mut ball = sim.balls[0].clone();
+    let serve = trail_length(&ball, &sim.settings);
+    ball.velocity = ball.velocity * 2.0;
+
+    assert!(serve > 1);
+    assert!(trail_length(&ball, &sim.settings) > serve);
+    ball.velocity = ball.velocity * 10.0;
+    assert_eq!(TRAIL_LENGTH, trail_length(&ball, &sim.settings));
+  }
+}